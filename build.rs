@@ -3,7 +3,7 @@ use std::process::Command;
 fn main() -> std::io::Result<()> {
     let out_dir = env::var("OUT_DIR").unwrap();
     Command::new("elm")
-        .args(&[
+        .args([
             "make",
             "--output",
             &format!("{}/index.js", out_dir),
@@ -1,12 +1,13 @@
 port module Main exposing (..)
 
 import Browser exposing (Document)
-import Html exposing (Html, a, div, span, text, header, main_, section, ul, li)
-import Html.Attributes exposing (class, href, style)
-import Html.Events exposing (onClick)
+import Html exposing (Html, a, button, div, h3, span, text, textarea, header, main_, option, section, select, table, tbody, td, tr, ul, li)
+import Html.Attributes exposing (class, classList, disabled, draggable, href, selected, style, value)
+import Html.Events exposing (on, onClick, onInput, preventDefaultOn)
 import Time exposing (every, toYear, toMonth, toDay, toHour, toMinute, toSecond, Posix)
 import Http
 import Json.Decode as D
+import Json.Encode as E
 import Time
 import Task
 import Svg exposing (svg, rect, title, desc, g, line, text_)
@@ -27,6 +28,9 @@ main =
 
 port notify : String -> Cmd msg
 
+-- events pushed from server through EventSource
+port events : (D.Value -> msg) -> Sub msg
+
 -- MODEL
 
 type alias Id = Int
@@ -35,19 +39,85 @@ type alias Model =
   { now : Posix
   , timeZone : Maybe Time.Zone
   , timer : Maybe Timer
+  , tasks : List TaskItem
+  , selectedTaskId : Maybe Id
+  , plan : Maybe Plan
+  , noteDraft : String
+  , dragging : Maybe Id
   , pomodoroDaily: Maybe Measurements
   , errorMsg : Maybe String
   , loading: Bool
+  , page : Page
+  , board : List Column
+  , priorities : List PriorityItem
+  , draggingCard : Maybe Id
   }
 
+type Page
+  = TimerPage
+  | BoardPage
+
 type alias Timer =
   { id: Id
   , timer_type: Int
   , label: String
   , startedAt: Posix
   , durationMin: Int
+  , pausedAt: Maybe Posix
+  , pausedSec: Int
+  }
+
+-- candidate of pomodoro to start
+type alias TaskItem =
+  { id: Id
+  , summary: String
+  }
+
+type alias TodoItem =
+  { taskId: Id
+  , summary: String
+  , estimate: Int
+  , actual: Int
+  }
+
+-- todo of today
+type alias Plan =
+  { note: String
+  , tasks: List TodoItem
+  }
+
+-- task on the board
+type alias Card =
+  { id: Id
+  , summary: String
+  , priority: Id
+  , estimate: Int
+  , pomodoros: Int
+  }
+
+-- lane of the board with its tasks
+type alias Column =
+  { laneId: Id
+  , laneName: String
+  , cards: List Card
+  }
+
+type alias PriorityItem =
+  { id: Id
+  , name: String
   }
 
+-- failure of API call. Error status comes with the message sent by server
+type ApiError
+  = ServerError Int String
+  | HttpError Http.Error
+
+type ServerEvent
+  = TimerChanged Timer
+  | TimerFinished String Timer
+  | PlanChanged
+  | Reconnected
+
 type alias Measuremet =
   { time: Posix
   , value: Float
@@ -156,11 +226,27 @@ init _ =
   ( { now = Time.millisToPosix 0
     , timeZone = Nothing
     , timer = Nothing
+    , tasks = []
+    , selectedTaskId = Nothing
+    , plan = Nothing
+    , noteDraft = ""
+    , dragging = Nothing
     , pomodoroDaily = Nothing
     , errorMsg = Nothing
     , loading = False
+    , page = TimerPage
+    , board = []
+    , priorities = []
+    , draggingCard = Nothing
     }
-    , Task.perform SetTimeZone Time.here
+    , Cmd.batch
+      [ Task.perform SetTimeZone Time.here
+      , Task.perform Refresh Time.now
+      , Http.get
+          { url = "/api/tasks"
+          , expect = expectJson TasksLoaded (D.list decodeTaskItem)
+          }
+      ]
   )
 
 
@@ -170,24 +256,125 @@ init _ =
 
 type Msg
   = Tick Posix
+  | Refresh Posix
+  | ServerEvent D.Value
   | TimerSuccess Timer
   | TimerFailure String
   | TimerNotFound
+  | TasksLoaded (Result ApiError (List TaskItem))
+  | SelectTask String
+  | StartPomodoro
+  | StartBreak String
+  | CompleteTimer
+  | StopTimer Bool
+  | TimerStarted (Result ApiError Timer)
+  | TimerStopped (Result ApiError Timer)
+  | PlanLoaded (Result ApiError Plan)
+  | PlanTask
+  | UnplanTask Id
+  | DragStart Id
+  | DragOver
+  | DragEnd
+  | DropOn Id
+  | NoteInput String
+  | SaveNote
   | MeasurementsSuccess Measurements
   | MeasurementsFailure String
   | SetTimeZone Time.Zone
+  | ShowPage Page
+  | BoardLoaded (Result ApiError (List Column))
+  | PrioritiesLoaded (Result ApiError (List PriorityItem))
+  | CardDragStart Id
+  | DropOnLane Id
+  | ChangePriority Id String
+  | TaskPatched (Result ApiError ())
 
 posix : D.Decoder Posix
 posix = D.map Time.millisToPosix D.int
 
 decodeTimer : D.Decoder Timer
 decodeTimer =
-  D.map5 Timer
+  D.map7 Timer
     (D.field "id" D.int)
     (D.field "timer_type" D.int)
     (D.field "label" D.string)
     (D.field "started_at" posix)
     (D.field "duration_min" D.int)
+    (D.field "paused_at" (D.nullable posix))
+    (D.field "paused_sec" D.int)
+
+decodeTaskItem : D.Decoder TaskItem
+decodeTaskItem =
+  D.map2 TaskItem
+    (D.field "id" D.int)
+    (D.field "summary" D.string)
+
+decodeTodoItem : D.Decoder TodoItem
+decodeTodoItem =
+  D.map4 TodoItem
+    (D.field "task_id" D.int)
+    (D.field "summary" D.string)
+    (D.field "estimate" D.int)
+    (D.field "actual" D.int)
+
+decodePlan : D.Decoder Plan
+decodePlan =
+  D.map2 Plan
+    (D.field "note" D.string)
+    (D.field "tasks" (D.list decodeTodoItem))
+
+decodeCard : D.Decoder Card
+decodeCard =
+  D.map5 Card
+    (D.field "id" D.int)
+    (D.field "summary" D.string)
+    (D.field "priority" D.int)
+    (D.field "estimate" D.int)
+    (D.field "pomodoros" D.int)
+
+decodeColumn : D.Decoder Column
+decodeColumn =
+  D.map3 Column
+    (D.at [ "lane", "id" ] D.int)
+    (D.at [ "lane", "name" ] D.string)
+    (D.field "tasks" (D.list decodeCard))
+
+decodePriorityItem : D.Decoder PriorityItem
+decodePriorityItem =
+  D.map2 PriorityItem
+    (D.field "id" D.int)
+    (D.field "name" D.string)
+
+timerEvent : D.Decoder ServerEvent
+timerEvent = D.map TimerChanged (D.field "data" decodeTimer)
+
+finishEvent : String -> D.Decoder ServerEvent
+finishEvent outcome = D.map (TimerFinished outcome) (D.field "data" decodeTimer)
+
+-- timer_type 0 is pomodoro, others are breaks
+finishedMessage : Timer -> String
+finishedMessage timer =
+  if timer.timer_type == 0 then
+    "pomodoro completed"
+  else
+    "break finished"
+
+decodeServerEvent : D.Decoder ServerEvent
+decodeServerEvent =
+  D.field "type" D.string
+    |> D.andThen
+      (\eventType ->
+        case eventType of
+          "timer_started" -> timerEvent
+          "timer_paused" -> timerEvent
+          "timer_resumed" -> timerEvent
+          "timer_completed" -> finishEvent "completed"
+          "timer_voided" -> finishEvent "voided"
+          "timer_cancelled" -> finishEvent "cancelled"
+          "todo_changed" -> D.succeed PlanChanged
+          "connected" -> D.succeed Reconnected
+          other -> D.fail ("unknown event " ++ other)
+      )
 
 decodeMeasurement : D.Decoder Measuremet
 decodeMeasurement =
@@ -211,55 +398,202 @@ handleMeasurements result =
     Err _ ->
       MeasurementsFailure "failed"
 
-handleTimer : Result Http.Error Timer -> Msg
+handleTimer : Result ApiError Timer -> Msg
 handleTimer result =
   case result of
     Ok t ->
       TimerSuccess t
 
-    Err (Http.BadStatus status) ->
-      case status of
-        404 ->
-          TimerNotFound
-        other ->
-          TimerFailure ("Error" ++ String.fromInt other)
+    Err (ServerError 404 _) ->
+      TimerNotFound
 
-    Err (Http.BadUrl url) ->
-      TimerFailure url
+    Err error ->
+      TimerFailure (apiErrorString error)
 
-    Err Http.Timeout ->
-      TimerFailure "timeout"
+apiErrorString : ApiError -> String
+apiErrorString error =
+  case error of
+    ServerError _ message ->
+      message
 
-    Err Http.NetworkError ->
-      TimerFailure "network error"
+    HttpError httpError ->
+      httpErrorString httpError
 
-    Err (Http.BadBody body) ->
-      TimerFailure ("bad body: " ++ body)
+httpErrorString : Http.Error -> String
+httpErrorString error =
+  case error of
+    Http.BadStatus status ->
+      "Error" ++ String.fromInt status
 
-expectJson : (Result Http.Error a -> msg) -> D.Decoder a -> Http.Expect msg
-expectJson toMsg decoder =
-  Http.expectStringResponse toMsg <|
-    \response ->
-      case response of
-        Http.BadUrl_ url ->
-          Err (Http.BadUrl url)
+    Http.BadUrl url ->
+      url
+
+    Http.Timeout ->
+      "timeout"
+
+    Http.NetworkError ->
+      "network error"
 
-        Http.Timeout_ ->
-          Err Http.Timeout
+    Http.BadBody body ->
+      "bad body: " ++ body
 
-        Http.NetworkError_ ->
-          Err Http.NetworkError
+startTimer : E.Value -> Cmd Msg
+startTimer body =
+  Http.post
+    { url = "/api/timer"
+    , body = Http.jsonBody body
+    , expect = expectJson TimerStarted decodeTimer
+    }
 
-        Http.BadStatus_ metadata _ ->
-          Err (Http.BadStatus metadata.statusCode)
+-- void records interrupted pomodoro, otherwise the timer is discarded
+stopTimer : Bool -> Cmd Msg
+stopTimer void =
+  Http.request
+    { method = "DELETE"
+    , headers = []
+    , url = "/api/timer?void=" ++ (if void then "true" else "false")
+    , body = Http.emptyBody
+    , expect = expectJson TimerStopped decodeTimer
+    , timeout = Nothing
+    , tracker = Nothing
+    }
 
-        Http.GoodStatus_ _ body ->
-          case D.decodeString decoder body of
-            Ok value ->
-              Ok value
+completeTimer : Cmd Msg
+completeTimer =
+  Http.post
+    { url = "/api/timer/complete"
+    , body = Http.emptyBody
+    , expect = expectJson TimerStopped decodeTimer
+    }
 
-            Err err ->
-              Err (Http.BadBody (D.errorToString err))
+fetchTimer : Cmd Msg
+fetchTimer =
+  Http.get
+    { url = "/api/timer"
+    , expect = expectJson handleTimer decodeTimer
+    }
+
+fetchPomodoroDaily : Model -> Posix -> Cmd Msg
+fetchPomodoroDaily model now =
+  Http.get
+    { url = "/api/pomodoro_daily?" ++ (dailySummaryQueryParams model now)
+    , expect = Http.expectJson handleMeasurements decodeMeasurements
+    }
+
+todoUrl : String
+todoUrl = "/api/todo/today"
+
+fetchPlan : Cmd Msg
+fetchPlan =
+  Http.get
+    { url = todoUrl
+    , expect = expectJson PlanLoaded decodePlan
+    }
+
+putPlan : String -> E.Value -> Cmd Msg
+putPlan path body =
+  Http.request
+    { method = "PUT"
+    , headers = []
+    , url = todoUrl ++ path
+    , body = Http.jsonBody body
+    , expect = expectJson PlanLoaded decodePlan
+    , timeout = Nothing
+    , tracker = Nothing
+    }
+
+fetchBoard : Cmd Msg
+fetchBoard =
+  Http.get
+    { url = "/api/board"
+    , expect = expectJson BoardLoaded (D.list decodeColumn)
+    }
+
+fetchPriorities : Cmd Msg
+fetchPriorities =
+  Http.get
+    { url = "/api/priorities"
+    , expect = expectJson PrioritiesLoaded (D.list decodePriorityItem)
+    }
+
+-- board is fetched only while it is shown
+refreshBoard : Model -> Cmd Msg
+refreshBoard model =
+  if model.page == BoardPage then fetchBoard else Cmd.none
+
+patchTask : Id -> E.Value -> Cmd Msg
+patchTask taskId body =
+  Http.request
+    { method = "PATCH"
+    , headers = []
+    , url = "/api/tasks/" ++ String.fromInt taskId
+    , body = Http.jsonBody body
+    , expect = expectWhatever TaskPatched
+    , timeout = Nothing
+    , tracker = Nothing
+    }
+
+-- move dragged task to the position of target
+moveTo : Id -> Id -> List Id -> List Id
+moveTo dragged target ids =
+  let
+    position id =
+      List.indexedMap Tuple.pair ids
+        |> List.filter (\(_, i) -> i == id)
+        |> List.head
+        |> Maybe.map Tuple.first
+    downward = Maybe.map2 (<) (position dragged) (position target) == Just True
+    place i =
+      if i == target then
+        if downward then [ i, dragged ] else [ dragged, i ]
+      else
+        [ i ]
+  in
+    if dragged == target then
+      ids
+    else
+      List.concatMap place (List.filter (\i -> i /= dragged) ids)
+
+-- server sends error as {"message": ...}
+apiResponse : (String -> Result ApiError a) -> Http.Response String -> Result ApiError a
+apiResponse decodeBody response =
+  case response of
+    Http.BadUrl_ url ->
+      Err (HttpError (Http.BadUrl url))
+
+    Http.Timeout_ ->
+      Err (HttpError Http.Timeout)
+
+    Http.NetworkError_ ->
+      Err (HttpError Http.NetworkError)
+
+    Http.BadStatus_ metadata body ->
+      case D.decodeString (D.field "message" D.string) body of
+        Ok message ->
+          Err (ServerError metadata.statusCode message)
+
+        Err _ ->
+          Err (ServerError metadata.statusCode ("Error" ++ String.fromInt metadata.statusCode))
+
+    Http.GoodStatus_ _ body ->
+      decodeBody body
+
+expectJson : (Result ApiError a -> msg) -> D.Decoder a -> Http.Expect msg
+expectJson toMsg decoder =
+  Http.expectStringResponse toMsg <|
+    apiResponse
+      (\body ->
+        case D.decodeString decoder body of
+          Ok value ->
+            Ok value
+
+          Err err ->
+            Err (HttpError (Http.BadBody (D.errorToString err)))
+      )
+
+expectWhatever : (Result ApiError () -> msg) -> Http.Expect msg
+expectWhatever toMsg =
+  Http.expectStringResponse toMsg (apiResponse (\_ -> Ok ()))
 
 dailySummaryQueryParams : Model -> Time.Posix -> String
 dailySummaryQueryParams model now =
@@ -275,21 +609,42 @@ update : Msg -> Model -> (Model, Cmd Msg)
 update msg model =
   case msg of
     Tick now ->
+      ({ model | now = now }, Cmd.none)
+
+    -- fallback for changes not pushed, e.g. made by CLI on local database
+    Refresh now ->
       ({ model | now = now }
       , Cmd.batch
-        [
-          Http.get
-            { url = "/api/timer"
-            , expect = expectJson handleTimer decodeTimer
-            }
-          , Http.get -- updateコスト高そうなので60秒に一回とかにする
-            -- timezoneも渡すようにして
-            { url = "/api/pomodoro_daily?" ++ (dailySummaryQueryParams model now)
-            , expect = Http.expectJson handleMeasurements decodeMeasurements
-            }
+        [ fetchTimer
+        , fetchPlan
+        -- timezoneも渡すようにして
+        , fetchPomodoroDaily model now
+        , refreshBoard model
         ]
       )
 
+    ServerEvent value ->
+      case D.decodeValue decodeServerEvent value of
+        Ok (TimerChanged currentTimer) ->
+          ({ model | timer = Just currentTimer, errorMsg = Nothing }, Cmd.none)
+
+        Ok (TimerFinished "completed" finished) ->
+          ({ model | timer = Nothing }
+          , Cmd.batch [ notify (finishedMessage finished), fetchPlan, fetchPomodoroDaily model model.now, refreshBoard model ]
+          )
+
+        Ok (TimerFinished _ _) ->
+          ({ model | timer = Nothing }, fetchPlan)
+
+        Ok PlanChanged ->
+          (model, fetchPlan)
+
+        Ok Reconnected ->
+          (model, Cmd.batch [ fetchTimer, fetchPlan ])
+
+        Err error ->
+          ({ model | errorMsg = Just (D.errorToString error) }, Cmd.none)
+
     TimerSuccess currentTimer ->
       ({ model | timer = Just currentTimer, errorMsg = Nothing }, Cmd.none)
 
@@ -299,10 +654,109 @@ update msg model =
     TimerNotFound ->
       ({ model | timer = Nothing, errorMsg = Nothing }
       , case model.timer of
-        Just(_) -> notify "pomodoro completed"
+        Just(finished) -> Cmd.batch [ notify (finishedMessage finished), fetchPlan ]
         Nothing -> Cmd.none
       )
 
+    TasksLoaded (Ok tasks) ->
+      ({ model | tasks = tasks }, Cmd.none)
+
+    TasksLoaded (Err error) ->
+      ({ model | errorMsg = Just (apiErrorString error) }, Cmd.none)
+
+    SelectTask idString ->
+      ({ model | selectedTaskId = String.toInt idString }, Cmd.none)
+
+    StartPomodoro ->
+      case model.selectedTaskId of
+        Just taskId ->
+          (model, startTimer (E.object [ ("task_id", E.int taskId) ]))
+        Nothing ->
+          (model, Cmd.none)
+
+    StartBreak breakType ->
+      (model, startTimer (E.object [ ("break_type", E.string breakType) ]))
+
+    CompleteTimer ->
+      (model, completeTimer)
+
+    StopTimer void ->
+      (model, stopTimer void)
+
+    TimerStarted (Ok currentTimer) ->
+      ({ model | timer = Just currentTimer, errorMsg = Nothing }, Cmd.none)
+
+    TimerStarted (Err error) ->
+      ({ model | errorMsg = Just (apiErrorString error) }, Cmd.none)
+
+    -- clear timer here so that TimerNotFound does not notify completion
+    TimerStopped (Ok _) ->
+      ({ model | timer = Nothing, errorMsg = Nothing }, fetchPlan)
+
+    TimerStopped (Err error) ->
+      ({ model | errorMsg = Just (apiErrorString error) }, Cmd.none)
+
+    PlanLoaded (Ok plan) ->
+      let
+        -- keep note being edited
+        draft = if model.plan == Nothing || Maybe.map .note model.plan == Just model.noteDraft then plan.note else model.noteDraft
+      in
+        ({ model | plan = Just plan, noteDraft = draft }, Cmd.none)
+
+    PlanLoaded (Err error) ->
+      ({ model | errorMsg = Just (apiErrorString error) }, Cmd.none)
+
+    PlanTask ->
+      case model.selectedTaskId of
+        Just taskId ->
+          ( model
+          , Http.post
+              { url = todoUrl ++ "/tasks"
+              , body = Http.jsonBody (E.object [ ("task_id", E.int taskId) ])
+              , expect = expectJson PlanLoaded decodePlan
+              }
+          )
+        Nothing ->
+          (model, Cmd.none)
+
+    UnplanTask taskId ->
+      ( model
+      , Http.request
+          { method = "DELETE"
+          , headers = []
+          , url = todoUrl ++ "/tasks/" ++ String.fromInt taskId
+          , body = Http.emptyBody
+          , expect = expectJson PlanLoaded decodePlan
+          , timeout = Nothing
+          , tracker = Nothing
+          }
+      )
+
+    DragStart taskId ->
+      ({ model | dragging = Just taskId }, Cmd.none)
+
+    DragOver ->
+      (model, Cmd.none)
+
+    DragEnd ->
+      ({ model | dragging = Nothing, draggingCard = Nothing }, Cmd.none)
+
+    DropOn target ->
+      case (model.dragging, model.plan) of
+        (Just dragged, Just plan) ->
+          let
+            ids = moveTo dragged target (List.map .taskId plan.tasks)
+          in
+            ({ model | dragging = Nothing }, putPlan "/tasks" (E.list E.int ids))
+        _ ->
+          ({ model | dragging = Nothing }, Cmd.none)
+
+    NoteInput note ->
+      ({ model | noteDraft = note }, Cmd.none)
+
+    SaveNote ->
+      (model, putPlan "/note" (E.object [ ("note", E.string model.noteDraft) ]))
+
     MeasurementsSuccess measurements ->
       if measurements.instrument == "pomodoro.daily" then
         ({ model | pomodoroDaily = Just measurements }, Cmd.none)
@@ -315,12 +769,62 @@ update msg model =
     SetTimeZone zone ->
       ({ model | timeZone = Just zone }, Cmd.none)
 
+    ShowPage BoardPage ->
+      ({ model | page = BoardPage }, Cmd.batch [ fetchBoard, fetchPriorities ])
+
+    ShowPage page ->
+      ({ model | page = page }, Cmd.none)
+
+    BoardLoaded (Ok columns) ->
+      ({ model | board = columns }, Cmd.none)
+
+    BoardLoaded (Err error) ->
+      ({ model | errorMsg = Just (apiErrorString error) }, Cmd.none)
+
+    PrioritiesLoaded (Ok priorities) ->
+      ({ model | priorities = priorities }, Cmd.none)
+
+    PrioritiesLoaded (Err error) ->
+      ({ model | errorMsg = Just (apiErrorString error) }, Cmd.none)
+
+    CardDragStart taskId ->
+      ({ model | draggingCard = Just taskId }, Cmd.none)
+
+    DropOnLane laneId ->
+      case model.draggingCard of
+        Just taskId ->
+          ({ model | draggingCard = Nothing }, patchTask taskId (E.object [ ("lane_id", E.int laneId) ]))
+        Nothing ->
+          (model, Cmd.none)
+
+    ChangePriority taskId idString ->
+      case String.toInt idString of
+        Just priorityId ->
+          (model, patchTask taskId (E.object [ ("priority", E.int priorityId) ]))
+        Nothing ->
+          (model, Cmd.none)
+
+    TaskPatched (Ok _) ->
+      ({ model | errorMsg = Nothing }, fetchBoard)
+
+    TaskPatched (Err error) ->
+      ({ model | errorMsg = Just (apiErrorString error) }, fetchBoard)
+
 -- VIEW
 
 padZero : Int -> String
 padZero x =
   if x >= 10 then String.fromInt x else "0" ++ String.fromInt x
 
+remainingSeconds : Posix -> Timer -> Int
+remainingSeconds now currentTimer =
+  let
+    -- paused timer stops counting down at the time it was paused
+    until = Maybe.withDefault now currentTimer.pausedAt
+    duration = (Time.posixToMillis until) - (Time.posixToMillis currentTimer.startedAt) - (currentTimer.pausedSec * 1000)
+  in
+    currentTimer.durationMin * 60 - duration // 1000
+
 timer : Model -> Html Msg
 timer model =
   case model.timer of
@@ -328,14 +832,107 @@ timer model =
       text "00:00"
     Just currentTimer ->
       let
-        duration = (Time.posixToMillis model.now) - (Time.posixToMillis currentTimer.startedAt)
-        maxSeconds = currentTimer.durationMin * 60
-        durationSeconds = duration // 1000
-        remainingSeconds = maxSeconds - durationSeconds
-        minutes = if 0 <= remainingSeconds then remainingSeconds // 60 else 0
-        seconds = if 0 <= remainingSeconds then modBy 60 remainingSeconds else 0
+        remaining = remainingSeconds model.now currentTimer
+        minutes = if 0 <= remaining then remaining // 60 else 0
+        seconds = if 0 <= remaining then modBy 60 remaining else 0
+        paused = if currentTimer.pausedAt == Nothing then "" else " (paused)"
+      in
+        text <| (padZero minutes) ++ ":" ++ (padZero seconds) ++ paused
+
+timerControls : Model -> Html Msg
+timerControls model =
+  case model.timer of
+    Just currentTimer ->
+      let
+        -- pomodoro is completed only at its deadline, break at any time
+        early = currentTimer.timer_type == 0 && remainingSeconds model.now currentTimer > 0
+      in
+        div [ class "pure-form" ]
+          [ button [ class "pure-button", class "pure-button-primary", disabled early, onClick CompleteTimer ] [ text "Complete" ]
+          , button [ class "pure-button", onClick (StopTimer True) ] [ text "Void" ]
+          , button [ class "pure-button", onClick (StopTimer False) ] [ text "Cancel" ]
+          ]
+    Nothing ->
+      let
+        taskOption t =
+          option [ value (String.fromInt t.id), selected (model.selectedTaskId == Just t.id) ] [ text t.summary ]
       in
-        text <| (padZero minutes) ++ ":" ++ (padZero seconds)
+        div [ class "pure-form" ]
+          [ select [ onInput SelectTask ] (option [ value "" ] [ text "-- task --" ] :: List.map taskOption model.tasks)
+          , button [ class "pure-button", class "pure-button-primary", disabled (model.selectedTaskId == Nothing), onClick StartPomodoro ] [ text "Start" ]
+          , button [ class "pure-button", disabled (model.selectedTaskId == Nothing), onClick PlanTask ] [ text "Add to todo" ]
+          , button [ class "pure-button", onClick (StartBreak "short") ] [ text "Short break" ]
+          , button [ class "pure-button", onClick (StartBreak "long") ] [ text "Long break" ]
+          ]
+
+todoRow : TodoItem -> Html Msg
+todoRow item =
+  tr
+    [ draggable "true"
+    , on "dragstart" (D.succeed (DragStart item.taskId))
+    , on "dragend" (D.succeed DragEnd)
+    , preventDefaultOn "dragover" (D.succeed (DragOver, True))
+    , preventDefaultOn "drop" (D.succeed (DropOn item.taskId, True))
+    , style "cursor" "move"
+    ]
+    [ td [] [ text (String.fromInt item.taskId) ]
+    , td [] [ text item.summary ]
+    , td [] [ text (String.fromInt item.actual ++ "/" ++ String.fromInt item.estimate) ]
+    , td [] [ button [ class "pure-button", onClick (UnplanTask item.taskId) ] [ text "×" ] ]
+    ]
+
+renderTodo : Model -> Html Msg
+renderTodo model =
+  case model.plan of
+    Nothing ->
+      div [] [ text "Loading todo" ]
+    Just plan ->
+      div [ class "pure-form" ]
+        [ table [ class "pure-table", style "width" "100%" ] [ tbody [] (List.map todoRow plan.tasks) ]
+        , textarea [ value model.noteDraft, onInput NoteInput, style "width" "100%" ] []
+        , button [ class "pure-button", onClick SaveNote ] [ text "Save note" ]
+        ]
+
+boardCard : List PriorityItem -> Card -> Html Msg
+boardCard priorities card =
+  let
+    priorityOption p =
+      option [ value (String.fromInt p.id), selected (p.id == card.priority) ] [ text p.name ]
+  in
+    div
+      [ draggable "true"
+      , on "dragstart" (D.succeed (CardDragStart card.id))
+      , on "dragend" (D.succeed DragEnd)
+      , class "pure-form"
+      , style "cursor" "move"
+      , style "border" "1px solid #ddd"
+      , style "border-radius" "4px"
+      , style "padding" ".5em"
+      , style "margin-bottom" ".5em"
+      ]
+      [ div [] [ text (String.fromInt card.id ++ " " ++ card.summary) ]
+      , select [ onInput (ChangePriority card.id) ] (List.map priorityOption priorities)
+      -- completed pomodoros against estimate as todo shows
+      , span [ style "margin-left" ".5em" ] [ text (String.fromInt card.pomodoros ++ "/" ++ String.fromInt card.estimate) ]
+      ]
+
+boardColumn : Model -> Column -> Html Msg
+boardColumn model column =
+  div
+    [ preventDefaultOn "dragover" (D.succeed (DragOver, True))
+    , preventDefaultOn "drop" (D.succeed (DropOnLane column.laneId, True))
+    , style "flex" "1"
+    , style "min-width" "12em"
+    , style "min-height" "10em"
+    ]
+    (h3 [] [ text (column.laneName ++ " (" ++ String.fromInt (List.length column.cards) ++ ")") ]
+      :: List.map (boardCard model.priorities) column.cards
+    )
+
+-- lanes side by side in process order, cards are dropped on a lane to move
+renderBoard : Model -> Html Msg
+renderBoard model =
+  div [ style "display" "flex", style "gap" "1em", style "overflow-x" "auto" ] (List.map (boardColumn model) model.board)
 
 weekdayString : Time.Weekday -> String
 weekdayString weekday =
@@ -437,6 +1034,24 @@ renderPomodoroDaily model =
      Just chart -> renderBarChart chart
      Nothing -> div [] [ text "No pomodoro activities" ]
 
+menuItem : Model -> Page -> String -> Html Msg
+menuItem model page label =
+  li
+    [ class "pure-menu-item", classList [ ("pure-menu-selected", model.page == page) ] ]
+    [ a [ class "pure-menu-link", href "#", onClick (ShowPage page) ] [ text label ] ]
+
+pageSections : Model -> List (Html Msg)
+pageSections model =
+  case model.page of
+    TimerPage ->
+      [ section [ class "pure-g" ] [ div [ class "pure-u-1" ] [ timerControls model ] ]
+      , section [ class "pure-g" ] [ div [ class "pure-u-1" ] [ renderTodo model ] ]
+      , section [ class "pure-g" ] [ div [ class "pure-u-1" ] [ renderPomodoroDaily model ] ]
+      ]
+
+    BoardPage ->
+      [ section [ class "pure-g" ] [ div [ class "pure-u-1" ] [ renderBoard model ] ] ]
+
 view : Model -> Document Msg
 view model =
   Document 
@@ -445,18 +1060,25 @@ view model =
         [ div [ class "pure-menu", class "pure-menu-horizontal", class "pure-menu-fixed" ]
             [ a [ class "pure-menu-heading", href "#" ] [ text "ly" ]
             , ul [ class "pure-menu-list" ]
-                [ li [ class "pure-menu-item" ] [ span [ style "padding" ".5em 1em" ] [ timer model ] ]
+                [ menuItem model TimerPage "Timer"
+                , menuItem model BoardPage "Board"
+                , li [ class "pure-menu-item" ] [ span [ style "padding" ".5em 1em" ] [ timer model ] ]
                 , li [ class "pure-menu-item" ] [ span [ style "padding" ".5em 1em" ] [ text (Maybe.withDefault "" (Maybe.map (\t -> t.label) model.timer)) ] ]
                 ]
             ]
         ]
     , main_ [ style "width" "100%", style "padding-top" "45px", style "padding-right" "15px", style "padding-left" "15px", style "margin-right" "auto", style "margin-left" "auto" ]
-        [ section [ class "pure-g" ] [ div [ class "pure-u-1" ] [ renderPomodoroDaily model ] ]
-        , section [ class "pure-g" ] [ div [ class "pure-u-1" ] [ text (Maybe.withDefault "" model.errorMsg) ] ]
-        ]
+        (pageSections model ++
+          [ section [ class "pure-g" ] [ div [ class "pure-u-1" ] [ text (Maybe.withDefault "" model.errorMsg) ] ] ]
+        )
     ]
   
 
 
 subscriptions : Model -> Sub Msg
-subscriptions _ = every 1000 Tick
+subscriptions _ =
+  Sub.batch
+    [ every 1000 Tick
+    , every 60000 Refresh
+    , events ServerEvent
+    ]
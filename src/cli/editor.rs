@@ -0,0 +1,39 @@
+use anyhow::{anyhow, Context, Result};
+use std::env;
+use std::fs;
+use std::process::Command;
+
+/// Editor to launch: `$VISUAL`, `$EDITOR`, or `vi`.
+fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|key| env::var(key).ok())
+        .find(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+/// Opens `content` in editor and returns the saved text.
+/// Editor is run through shell so that it can have arguments like `code --wait`.
+pub fn edit(content: &str, suffix: &str) -> Result<String> {
+    let path = env::temp_dir().join(format!("ly-{}{}", uuid::Uuid::new_v4(), suffix));
+    fs::write(&path, content)
+        .with_context(|| format!("Failed to write temporary file {}", path.display()))?;
+    let editor = editor();
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Failed to launch editor {}", editor));
+    let edited = match status {
+        Ok(s) if s.success() => fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read temporary file {}", path.display())),
+        Ok(s) => Err(anyhow!("Editor {} exited with {}", editor, s)),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = fs::remove_file(&path) {
+        warn!("failed to remove {}: {}", path.display(), e);
+    }
+    edited
+}
@@ -1,25 +1,80 @@
+use crate::core::breaks;
+use crate::core::estimate;
+use crate::core::interruption;
 use crate::core::lane;
 use crate::core::priority;
+use crate::core::tag;
 use crate::core::task;
 use crate::core::todo;
 use crate::core::Id;
+use anyhow::{bail, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use clap::ArgEnum;
+use serde::Serialize;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt::Display;
+
+pub mod editor;
+mod pretty;
+
+use self::pretty::Cell;
 
 static UNKNOWN: &str = "UNKNOWN";
 
+/// Format of listings.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ArgEnum)]
+pub enum Output {
+    Json,
+    Csv,
+    Tsv,
+    Table,
+    /// Aligned table with colors, fitted to terminal width
+    Pretty,
+}
+
+impl Output {
+    /// Pretty table on terminal, otherwise TSV which `ly todo load` reads back.
+    pub fn detect() -> Output {
+        if pretty::is_terminal() {
+            Output::Pretty
+        } else {
+            Output::Tsv
+        }
+    }
+}
+
 pub trait FormatWithLanePriority {
     fn format(
         &self,
         lanes: &HashMap<Id, &lane::Lane>,
         priorities: &HashMap<Id, &priority::Priority>,
+        tags: &HashMap<Id, Vec<String>>,
     ) -> String;
 }
 
+/// Appends comma separated tag names as the last column, empty when the task has none,
+/// so that every row of a listing has the same columns.
+fn append_tags(line: String, task_id: &Id, tags: &HashMap<Id, Vec<String>>) -> String {
+    let names = tags.get(task_id).map(|t| t.join(",")).unwrap_or_default();
+    format!("{}\t{}", line, names)
+}
+
+/// Pomodoro Technique notation: ' for internal and - for external interruption
+fn interruption_marks(internal: i64, external: i64) -> String {
+    format!(
+        "{}{}",
+        "'".repeat(internal as usize),
+        "-".repeat(external as usize)
+    )
+}
+
 impl FormatWithLanePriority for task::Task {
     fn format(
         &self,
         lanes: &HashMap<Id, &lane::Lane>,
         priorities: &HashMap<Id, &priority::Priority>,
+        tags: &HashMap<Id, Vec<String>>,
     ) -> String {
         let lane_name = lanes
             .get(&self.lane_id)
@@ -29,10 +84,11 @@ impl FormatWithLanePriority for task::Task {
             .get(&self.priority)
             .map(|p| p.name.as_ref())
             .unwrap_or(UNKNOWN);
-        format!(
+        let line = format!(
             "{}\t{}\t{}\t{}\t{}",
             self.id, lane_name, priority_name, self.estimate, self.summary
-        )
+        );
+        append_tags(line, &self.id, tags)
     }
 }
 
@@ -41,6 +97,7 @@ impl FormatWithLanePriority for todo::TodoTask {
         &self,
         lanes: &HashMap<Id, &lane::Lane>,
         priorities: &HashMap<Id, &priority::Priority>,
+        tags: &HashMap<Id, Vec<String>>,
     ) -> String {
         let lane_name = lanes
             .get(&self.lane_id)
@@ -50,16 +107,403 @@ impl FormatWithLanePriority for todo::TodoTask {
             .get(&self.priority)
             .map(|p| p.name.as_ref())
             .unwrap_or(UNKNOWN);
-        format!(
+        let interruptions =
+            interruption_marks(self.internal_interruptions, self.external_interruptions);
+        let line = format!(
             "{}\t{}\t{}\t{}/{}\t{}",
             self.task_id, lane_name, priority_name, self.actual, self.estimate, self.summary
+        );
+        // columns added later follow existing ones to keep scripts parsing them working
+        format!(
+            "{}\t{}",
+            append_tags(line, &self.task_id, tags),
+            interruptions
         )
     }
 }
 
+/// Row of listing which belongs to a lane and has priority.
+pub trait Listing: FormatWithLanePriority + Serialize {
+    fn task_id(&self) -> Id;
+    fn lane_id(&self) -> Id;
+    fn priority_id(&self) -> Id;
+    /// Column names for CSV and table
+    fn header() -> Vec<&'static str>;
+    /// Values in the order of [`Listing::header`]
+    fn values(&self, lane_name: &str, priority_name: &str, tags: &[String]) -> Vec<String>;
+    /// Column names for pretty table. Summary comes last so that it can be truncated.
+    fn pretty_header() -> Vec<&'static str>;
+    /// Cells in the order of [`Listing::pretty_header`]
+    fn cells(&self, lane_name: &str, priority: Cell, tags: &[String]) -> Vec<Cell>;
+}
+
+impl Listing for task::Task {
+    fn task_id(&self) -> Id {
+        self.id
+    }
+    fn lane_id(&self) -> Id {
+        self.lane_id
+    }
+    fn priority_id(&self) -> Id {
+        self.priority
+    }
+    fn header() -> Vec<&'static str> {
+        vec!["id", "lane", "priority", "estimate", "summary", "tags"]
+    }
+    fn values(&self, lane_name: &str, priority_name: &str, tags: &[String]) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            lane_name.to_string(),
+            priority_name.to_string(),
+            self.estimate.to_string(),
+            self.summary.clone(),
+            tags.join(","),
+        ]
+    }
+    fn pretty_header() -> Vec<&'static str> {
+        vec!["id", "lane", "priority", "est", "tags", "summary"]
+    }
+    fn cells(&self, lane_name: &str, priority: Cell, tags: &[String]) -> Vec<Cell> {
+        vec![
+            Cell::plain(self.id.to_string()),
+            Cell::plain(lane_name),
+            priority,
+            Cell::plain(self.estimate.to_string()),
+            Cell::plain(tags.join(",")),
+            Cell::plain(self.summary.as_str()),
+        ]
+    }
+}
+
+impl Listing for todo::TodoTask {
+    fn task_id(&self) -> Id {
+        self.task_id
+    }
+    fn lane_id(&self) -> Id {
+        self.lane_id
+    }
+    fn priority_id(&self) -> Id {
+        self.priority
+    }
+    fn header() -> Vec<&'static str> {
+        vec![
+            "id", "lane", "priority", "actual", "estimate", "internal", "external", "summary",
+            "tags",
+        ]
+    }
+    fn values(&self, lane_name: &str, priority_name: &str, tags: &[String]) -> Vec<String> {
+        vec![
+            self.task_id.to_string(),
+            lane_name.to_string(),
+            priority_name.to_string(),
+            self.actual.to_string(),
+            self.estimate.to_string(),
+            self.internal_interruptions.to_string(),
+            self.external_interruptions.to_string(),
+            self.summary.clone(),
+            tags.join(","),
+        ]
+    }
+    fn pretty_header() -> Vec<&'static str> {
+        vec![
+            "id", "lane", "priority", "progress", "int", "tags", "summary",
+        ]
+    }
+    fn cells(&self, lane_name: &str, priority: Cell, tags: &[String]) -> Vec<Cell> {
+        vec![
+            Cell::plain(self.task_id.to_string()),
+            Cell::plain(lane_name),
+            priority,
+            pretty::progress(self.actual, self.estimate),
+            Cell::plain(interruption_marks(
+                self.internal_interruptions,
+                self.external_interruptions,
+            )),
+            Cell::plain(tags.join(",")),
+            Cell::plain(self.summary.as_str()),
+        ]
+    }
+}
+
+/// Row serialized as JSON with names of lane, priority and tags.
+#[derive(Serialize)]
+pub struct Named<'a, T> {
+    #[serde(flatten)]
+    item: &'a T,
+    lane_name: &'a str,
+    priority_name: &'a str,
+    tags: &'a [String],
+}
+
+/// Quotes CSV field only when it is needed.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Pads columns to the widest value.
+fn align(header: &[&str], rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (w, v) in widths.iter_mut().zip(row) {
+            *w = (*w).max(v.chars().count());
+        }
+    }
+    let header: Vec<String> = header.iter().map(|h| h.to_uppercase()).collect();
+    std::iter::once(&header)
+        .chain(rows)
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(v, w)| format!("{:<width$}", v, width = w))
+                .collect::<Vec<String>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+/// Row of listing other than tasks, like lanes or estimates.
+pub trait Row: Serialize {
+    /// Column names for CSV and table
+    fn header() -> Vec<&'static str>;
+    /// Values in the order of [`Row::header`]. Times are shown in `timezone`.
+    fn values<Tz>(&self, timezone: &Tz) -> Vec<String>
+    where
+        Tz: TimeZone,
+        Tz::Offset: Display;
+}
+
+impl Row for lane::Lane {
+    fn header() -> Vec<&'static str> {
+        vec!["id", "name"]
+    }
+    fn values<Tz>(&self, _: &Tz) -> Vec<String>
+    where
+        Tz: TimeZone,
+        Tz::Offset: Display,
+    {
+        vec![self.id.to_string(), self.name.clone()]
+    }
+}
+
+impl Row for priority::Priority {
+    fn header() -> Vec<&'static str> {
+        vec!["id", "name", "alias"]
+    }
+    fn values<Tz>(&self, _: &Tz) -> Vec<String>
+    where
+        Tz: TimeZone,
+        Tz::Offset: Display,
+    {
+        vec![
+            self.id.to_string(),
+            self.name.clone(),
+            self.alias.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl Row for tag::Tag {
+    fn header() -> Vec<&'static str> {
+        vec!["id", "name"]
+    }
+    fn values<Tz>(&self, _: &Tz) -> Vec<String>
+    where
+        Tz: TimeZone,
+        Tz::Offset: Display,
+    {
+        vec![self.id.to_string(), self.name.clone()]
+    }
+}
+
+impl Row for interruption::Interruption {
+    fn header() -> Vec<&'static str> {
+        vec!["id", "task_id", "kind", "created_at", "note"]
+    }
+    fn values<Tz>(&self, timezone: &Tz) -> Vec<String>
+    where
+        Tz: TimeZone,
+        Tz::Offset: Display,
+    {
+        let kind = if self.external {
+            "external"
+        } else {
+            "internal"
+        };
+        vec![
+            self.id.to_string(),
+            self.task_id.to_string(),
+            kind.to_string(),
+            self.created_at.with_timezone(timezone).to_rfc3339(),
+            self.note.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl Row for estimate::Estimate {
+    fn header() -> Vec<&'static str> {
+        vec!["created_at", "value"]
+    }
+    fn values<Tz>(&self, timezone: &Tz) -> Vec<String>
+    where
+        Tz: TimeZone,
+        Tz::Offset: Display,
+    {
+        vec![
+            self.created_at.with_timezone(timezone).to_rfc3339(),
+            self.value.to_string(),
+        ]
+    }
+}
+
+impl Row for estimate::Accuracy {
+    fn header() -> Vec<&'static str> {
+        vec!["task_id", "first", "last", "actual", "revisions", "summary"]
+    }
+    fn values<Tz>(&self, _: &Tz) -> Vec<String>
+    where
+        Tz: TimeZone,
+        Tz::Offset: Display,
+    {
+        vec![
+            self.task_id.to_string(),
+            self.first.to_string(),
+            self.last.to_string(),
+            self.actual.to_string(),
+            self.revisions.to_string(),
+            self.summary.clone(),
+        ]
+    }
+}
+
+/// Renders rows as lines in `output` format. JSON is a single array and TSV has no header.
+pub fn render_rows<T, Tz>(output: Output, items: &[T], timezone: &Tz) -> Result<Vec<String>>
+where
+    T: Row,
+    Tz: TimeZone,
+    Tz::Offset: Display,
+{
+    let values: Vec<Vec<String>> = items.iter().map(|t| t.values(timezone)).collect();
+    let lines = match output {
+        Output::Json => vec![serde_json::to_string_pretty(items)?],
+        Output::Csv => std::iter::once(T::header().join(","))
+            .chain(values.iter().map(|row| {
+                row.iter()
+                    .map(|v| csv_field(v))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            }))
+            .collect(),
+        Output::Tsv => values.iter().map(|row| row.join("\t")).collect(),
+        Output::Table => align(&T::header(), &values),
+        Output::Pretty => {
+            let header = T::header();
+            let table = pretty::Table {
+                flexible: header.len() - 1,
+                header,
+                rows: values
+                    .into_iter()
+                    .map(|row| row.into_iter().map(Cell::plain).collect())
+                    .collect(),
+            };
+            table.render(pretty::terminal_width(), pretty::use_color())
+        }
+    };
+    Ok(lines)
+}
+
+/// Renders accuracy report with counts of under, exact and over estimated tasks.
+/// Counts are omitted from JSON and CSV to keep them plain rows.
+pub fn render_accuracy<Tz>(
+    output: Output,
+    report: &[estimate::Accuracy],
+    timezone: &Tz,
+) -> Result<Vec<String>>
+where
+    Tz: TimeZone,
+    Tz::Offset: Display,
+{
+    let count = |o: Ordering| report.iter().filter(|a| a.outcome() == o).count();
+    let pairs = [
+        ("tasks", report.len()),
+        ("under", count(Ordering::Greater)),
+        ("exact", count(Ordering::Equal)),
+        ("over", count(Ordering::Less)),
+    ];
+    let mut lines = match output {
+        Output::Json | Output::Csv => vec![],
+        Output::Tsv => {
+            let header: Vec<String> = pairs.iter().map(|(k, v)| format!("{}:{}", k, v)).collect();
+            vec![format!("#{}", header.join("\t"))]
+        }
+        Output::Table | Output::Pretty => {
+            let header: Vec<String> = pairs.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+            vec![header.join("  "), String::new()]
+        }
+    };
+    lines.extend(render_rows(output, report, timezone)?);
+    Ok(lines)
+}
+
+/// Totals of todo on a date.
+#[derive(Serialize)]
+pub struct TodoSummary {
+    date: String,
+    estimate: i64,
+    actual: i64,
+    remaining: i64,
+    internal: i64,
+    external: i64,
+    breaks: usize,
+    rested_min: i64,
+}
+
+impl TodoSummary {
+    pub fn new(date: String, tasks: &[todo::TodoTask], breaks: &[breaks::Break]) -> TodoSummary {
+        let estimate = tasks.iter().fold(0, |s, t| s + t.estimate);
+        let actual = tasks.iter().fold(0, |s, t| s + t.actual);
+        TodoSummary {
+            date,
+            estimate,
+            actual,
+            remaining: estimate - actual,
+            internal: tasks.iter().fold(0, |s, t| s + t.internal_interruptions),
+            external: tasks.iter().fold(0, |s, t| s + t.external_interruptions),
+            breaks: breaks.len(),
+            rested_min: breaks.iter().fold(0, |s, b| s + b.rested_min()),
+        }
+    }
+
+    fn pairs(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("date", self.date.clone()),
+            ("estimate", self.estimate.to_string()),
+            ("actual", self.actual.to_string()),
+            ("remaining", self.remaining.to_string()),
+            ("internal", self.internal.to_string()),
+            ("external", self.external.to_string()),
+            ("breaks", self.breaks.to_string()),
+            ("rested_min", self.rested_min.to_string()),
+        ]
+    }
+}
+
+#[derive(Serialize)]
+struct TodoListing<'a> {
+    #[serde(flatten)]
+    summary: &'a TodoSummary,
+    tasks: Vec<Named<'a, todo::TodoTask>>,
+}
+
 pub struct TaskContext<'l, 'p> {
     lanes: HashMap<Id, &'l lane::Lane>,
     priorities: HashMap<Id, &'p priority::Priority>,
+    tags: HashMap<Id, Vec<String>>,
 }
 
 impl<'l, 'p> TaskContext<'l, 'p> {
@@ -78,9 +522,386 @@ impl<'l, 'p> TaskContext<'l, 'p> {
         TaskContext {
             lanes: lane_hash,
             priorities: priority_hash,
+            tags: HashMap::new(),
+        }
+    }
+    pub fn with_tags(mut self, task_tags: Vec<tag::TaskTag>) -> TaskContext<'l, 'p> {
+        for t in task_tags {
+            self.tags.entry(t.task_id).or_default().push(t.name);
+        }
+        self
+    }
+    pub fn format<T: FormatWithLanePriority>(&self, target: &T) -> String {
+        target.format(&self.lanes, &self.priorities, &self.tags)
+    }
+
+    fn lane_name(&self, id: Id) -> &str {
+        self.lanes
+            .get(&id)
+            .map(|l| l.name.as_ref())
+            .unwrap_or(UNKNOWN)
+    }
+
+    fn priority_name(&self, id: Id) -> &str {
+        self.priorities
+            .get(&id)
+            .map(|p| p.name.as_ref())
+            .unwrap_or(UNKNOWN)
+    }
+
+    fn priority_cell(&self, id: Id) -> Cell {
+        let rank = self.priorities.get(&id).map(|p| p.rank);
+        let mut ranks: Vec<i64> = self.priorities.values().map(|p| p.rank).collect();
+        ranks.sort_unstable_by(|a, b| b.cmp(a));
+        ranks.dedup();
+        match rank.and_then(|r| ranks.iter().position(|x| *x == r)) {
+            Some(position) => pretty::priority(self.priority_name(id), position, ranks.len()),
+            None => Cell::plain(UNKNOWN),
+        }
+    }
+
+    fn tags(&self, task_id: Id) -> &[String] {
+        self.tags.get(&task_id).map(|t| t.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn named<'a, T: Listing>(&'a self, item: &'a T) -> Named<'a, T> {
+        Named {
+            item,
+            lane_name: self.lane_name(item.lane_id()),
+            priority_name: self.priority_name(item.priority_id()),
+            tags: self.tags(item.task_id()),
+        }
+    }
+
+    /// Renders rows as lines in `output` format. JSON is a single array.
+    pub fn render<T: Listing>(&self, output: Output, items: &[T]) -> Result<Vec<String>> {
+        let values = || -> Vec<Vec<String>> {
+            items
+                .iter()
+                .map(|t| {
+                    let named = self.named(t);
+                    t.values(named.lane_name, named.priority_name, named.tags)
+                })
+                .collect()
+        };
+        let lines = match output {
+            Output::Json => {
+                let named: Vec<Named<T>> = items.iter().map(|t| self.named(t)).collect();
+                vec![serde_json::to_string_pretty(&named)?]
+            }
+            Output::Csv => std::iter::once(T::header().join(","))
+                .chain(values().iter().map(|row| {
+                    row.iter()
+                        .map(|v| csv_field(v))
+                        .collect::<Vec<String>>()
+                        .join(",")
+                }))
+                .collect(),
+            Output::Tsv => items.iter().map(|t| self.format(t)).collect(),
+            Output::Table => align(&T::header(), &values()),
+            Output::Pretty => {
+                let header = T::pretty_header();
+                let table = pretty::Table {
+                    flexible: header.len() - 1,
+                    header,
+                    rows: items
+                        .iter()
+                        .map(|t| {
+                            let named = self.named(t);
+                            let priority = self.priority_cell(t.priority_id());
+                            t.cells(named.lane_name, priority, named.tags)
+                        })
+                        .collect(),
+                };
+                table.render(pretty::terminal_width(), pretty::use_color())
+            }
+        };
+        Ok(lines)
+    }
+
+    /// Renders todo with its summary. Summary is omitted from CSV to keep it a plain table.
+    pub fn render_todo(
+        &self,
+        output: Output,
+        summary: &TodoSummary,
+        items: &[todo::TodoTask],
+    ) -> Result<Vec<String>> {
+        let pairs = summary.pairs();
+        let lines = match output {
+            Output::Json => {
+                let listing = TodoListing {
+                    summary,
+                    tasks: items.iter().map(|t| self.named(t)).collect(),
+                };
+                vec![serde_json::to_string_pretty(&listing)?]
+            }
+            Output::Csv => self.render(output, items)?,
+            Output::Tsv => {
+                let header: Vec<String> =
+                    pairs.iter().map(|(k, v)| format!("{}:{}", k, v)).collect();
+                let mut lines = vec![format!("#{}", header.join("\t"))];
+                lines.extend(self.render(output, items)?);
+                lines
+            }
+            Output::Table | Output::Pretty => {
+                let header: Vec<String> =
+                    pairs.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                let mut lines = vec![header.join("  "), String::new()];
+                lines.extend(self.render(output, items)?);
+                lines
+            }
+        };
+        Ok(lines)
+    }
+}
+
+/// Renders task with its history for `ly task show`.
+/// TSV and CSV records start with their kind, like `pomodoro` or `interruption`.
+pub fn render_detail<Tz>(
+    output: Output,
+    detail: &task::Detail,
+    timezone: &Tz,
+) -> Result<Vec<String>>
+where
+    Tz: TimeZone,
+    Tz::Offset: Display,
+{
+    if output == Output::Json {
+        return Ok(vec![serde_json::to_string_pretty(detail)?]);
+    }
+    let time = |t: &DateTime<Utc>| t.with_timezone(timezone).to_rfc3339();
+    let task = &detail.task;
+    let external = detail.interruptions.iter().filter(|i| i.external).count();
+    let mut fields = vec![
+        ("id", task.id.to_string()),
+        ("summary", task.summary.clone()),
+        ("lane", detail.lane_name.clone()),
+        ("priority", detail.priority_name.clone()),
+        ("estimate", task.estimate.to_string()),
+        ("actual", detail.actual.to_string()),
+        ("spent_min", (detail.spent_sec / 60).to_string()),
+        (
+            "internal",
+            (detail.interruptions.len() - external).to_string(),
+        ),
+        ("external", external.to_string()),
+        ("created_at", time(&task.created_at)),
+        ("updated_at", time(&task.updated_at)),
+    ];
+    if let Some(archived_at) = &task.archived_at {
+        fields.push(("archived_at", time(archived_at)));
+    }
+    let dates: Vec<String> = detail
+        .todo_dates
+        .iter()
+        .map(|d| d.with_timezone(timezone).format("%Y-%m-%d").to_string())
+        .collect();
+    fields.push(("todo", dates.join(" ")));
+    let pomodoros: Vec<Vec<String>> = detail
+        .pomodoros
+        .iter()
+        .map(|p| {
+            vec![
+                time(&p.started_at),
+                time(&p.finished_at),
+                p.outcome.to_string(),
+            ]
+        })
+        .collect();
+    let interruptions: Vec<Vec<String>> = detail
+        .interruptions
+        .iter()
+        .map(|i| {
+            let kind = if i.external { "external" } else { "internal" };
+            vec![
+                time(&i.created_at),
+                kind.to_string(),
+                i.note.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    let lines = match output {
+        Output::Csv | Output::Tsv => {
+            let record = |kind: &str, values: &[String]| -> String {
+                let values = std::iter::once(kind).chain(values.iter().map(|v| v.as_str()));
+                if output == Output::Csv {
+                    values.map(csv_field).collect::<Vec<String>>().join(",")
+                } else {
+                    values.collect::<Vec<&str>>().join("\t")
+                }
+            };
+            let mut lines: Vec<String> = fields
+                .into_iter()
+                .map(|(key, value)| record(key, &[value]))
+                .collect();
+            lines.extend(pomodoros.iter().map(|p| record("pomodoro", p)));
+            lines.extend(interruptions.iter().map(|i| record("interruption", i)));
+            lines
+        }
+        _ => {
+            let width = fields.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+            let mut lines: Vec<String> = fields
+                .into_iter()
+                .map(|(key, value)| format!("{:<width$}  {}", key, value, width = width))
+                .map(|line| line.trim_end().to_string())
+                .collect();
+            if !pomodoros.is_empty() {
+                lines.push(String::new());
+                lines.extend(align(&["started_at", "finished_at", "outcome"], &pomodoros));
+            }
+            if !interruptions.is_empty() {
+                lines.push(String::new());
+                lines.extend(align(&["created_at", "kind", "note"], &interruptions));
+            }
+            lines
+        }
+    };
+    Ok(lines)
+}
+
+/// Task ids read from the first column of lines with their line numbers.
+/// Blank lines and lines starting with `#` are skipped.
+pub fn parse_task_ids(input: &str) -> Result<Vec<(usize, Id)>> {
+    let mut ids = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let l = line.trim_start();
+        if l.is_empty() || l.starts_with('#') {
+            debug!("skip line {}: {:?}", i + 1, l);
+            continue;
         }
+        let id_str = l.split(char::is_whitespace).next().unwrap_or(l);
+        match id_str.parse::<Id>() {
+            Ok(id) => ids.push((i + 1, id)),
+            Err(_) => errors.push(format!("line {}: invalid task id {:?}", i + 1, id_str)),
+        }
+    }
+    if !errors.is_empty() {
+        bail!(errors.join("\n"));
     }
-    pub fn format<T: FormatWithLanePriority>(&self, target: T) -> String {
-        target.format(&self.lanes, &self.priorities)
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        align, append_tags, csv_field, parse_task_ids, render_detail, render_rows, Output,
+    };
+    use crate::core::interruption::Interruption;
+    use crate::core::lane::Lane;
+    use crate::core::pomodoro::{Outcome, Pomodoro};
+    use crate::core::task::{Detail, Task};
+    use chrono::{Duration, TimeZone, Utc};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_append_tags_keeps_columns() {
+        let mut tags = HashMap::new();
+        tags.insert(1, vec![String::from("a"), String::from("b")]);
+        assert_eq!(append_tags(String::from("1\tx"), &1, &tags), "1\tx\ta,b");
+        assert_eq!(append_tags(String::from("2\ty"), &2, &tags), "2\ty\t");
+    }
+
+    #[test]
+    fn test_align() {
+        let rows = vec![
+            vec![String::from("1"), String::from("write report")],
+            vec![String::from("12"), String::from("")],
+        ];
+        assert_eq!(
+            align(&["id", "summary"], &rows),
+            vec!["ID  SUMMARY", "1   write report", "12"]
+        );
+    }
+
+    #[test]
+    fn test_render_lanes_as_json() -> anyhow::Result<()> {
+        let at = Utc.ymd(2022, 5, 1).and_hms(9, 0, 0);
+        let lanes = vec![Lane {
+            id: 1,
+            name: String::from("backlog"),
+            lane_order: 1,
+            created_at: at,
+            updated_at: at,
+        }];
+        let lines = render_rows(Output::Json, &lanes, &Utc)?;
+        let json: serde_json::Value = serde_json::from_str(&lines.join("\n"))?;
+        assert_eq!(json[0]["id"], 1);
+        assert_eq!(json[0]["name"], "backlog");
+        assert_eq!(render_rows(Output::Tsv, &lanes, &Utc)?, vec!["1\tbacklog"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_task_ids() {
+        let input = "#date:2022-05-01\n3\tbacklog\tn\n\n# 4\tbacklog\n  5 in spaces\n";
+        assert_eq!(parse_task_ids(input).unwrap(), vec![(2, 3), (5, 5)]);
+    }
+
+    #[test]
+    fn test_parse_task_ids_reports_lines() {
+        let err = parse_task_ids("1\nfoo\tbar\n2\nx").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: invalid task id \"foo\"\nline 4: invalid task id \"x\""
+        );
+    }
+
+    #[test]
+    fn test_render_detail_records() -> anyhow::Result<()> {
+        let at = Utc.ymd(2022, 5, 1).and_hms(9, 0, 0);
+        let detail = Detail {
+            task: Task {
+                id: 3,
+                lane_id: 1,
+                priority: 3,
+                summary: String::from("write report"),
+                estimate: 2,
+                created_at: at,
+                updated_at: at,
+                archived_at: None,
+            },
+            lane_name: String::from("backlog"),
+            priority_name: String::from("high"),
+            actual: 1,
+            spent_sec: 25 * 60,
+            pomodoros: vec![Pomodoro {
+                id: 1,
+                task_id: 3,
+                started_at: at,
+                finished_at: at + Duration::minutes(25),
+                outcome: Outcome::Completed,
+                paused_sec: 0,
+            }],
+            interruptions: vec![Interruption {
+                id: 1,
+                task_id: 3,
+                external: true,
+                note: Some(String::from("phone call")),
+                created_at: at,
+            }],
+            todo_dates: vec![Utc.ymd(2022, 5, 1).and_hms(0, 0, 0)],
+        };
+        let lines = render_detail(Output::Tsv, &detail, &Utc)?;
+        assert_eq!(lines[5], "actual\t1");
+        assert_eq!(lines[6], "spent_min\t25");
+        assert_eq!(lines[8], "external\t1");
+        assert_eq!(lines[11], "todo\t2022-05-01");
+        assert_eq!(
+            lines[12],
+            "pomodoro\t2022-05-01T09:00:00+00:00\t2022-05-01T09:25:00+00:00\tcompleted"
+        );
+        assert_eq!(
+            lines[13],
+            "interruption\t2022-05-01T09:00:00+00:00\texternal\tphone call"
+        );
+        Ok(())
     }
 }
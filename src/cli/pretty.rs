@@ -0,0 +1,244 @@
+use std::io::IsTerminal;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Longest progress bar. Tasks estimated beyond this are shown as numbers only.
+const BAR_MAX: i64 = 10;
+const ELLIPSIS: char = '…';
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Color {
+    Red,
+    Yellow,
+    Green,
+    Dim,
+}
+
+impl Color {
+    fn code(&self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Yellow => "33",
+            Color::Green => "32",
+            Color::Dim => "2",
+        }
+    }
+}
+
+/// Value of table with optional color applied when printed.
+#[derive(Debug)]
+pub struct Cell {
+    text: String,
+    color: Option<Color>,
+}
+
+impl Cell {
+    pub fn plain<S: Into<String>>(text: S) -> Cell {
+        Cell {
+            text: text.into(),
+            color: None,
+        }
+    }
+
+    pub fn colored<S: Into<String>>(text: S, color: Option<Color>) -> Cell {
+        Cell {
+            text: text.into(),
+            color,
+        }
+    }
+}
+
+/// Aligned table for terminal. `flexible` column is truncated to fit in width.
+pub struct Table {
+    pub header: Vec<&'static str>,
+    pub rows: Vec<Vec<Cell>>,
+    pub flexible: usize,
+}
+
+fn paint(text: &str, color: Option<Color>, enabled: bool) -> String {
+    match color {
+        Some(c) if enabled => format!("\x1b[{}m{}\x1b[0m", c.code(), text),
+        _ => text.to_string(),
+    }
+}
+
+fn pad(text: &str, width: usize) -> String {
+    let fill = width.saturating_sub(text.width());
+    format!("{}{}", text, " ".repeat(fill))
+}
+
+/// Cuts text to `width` columns with ellipsis, counting wide characters as two.
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut result = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        used += w;
+        result.push(c);
+    }
+    result.push(ELLIPSIS);
+    result
+}
+
+impl Table {
+    /// Lines of table. Header is bold and values are colored when `color` is enabled.
+    pub fn render(&self, width: Option<usize>, color: bool) -> Vec<String> {
+        let mut widths: Vec<usize> = self.header.iter().map(|h| h.width()).collect();
+        for row in &self.rows {
+            for (w, cell) in widths.iter_mut().zip(row) {
+                *w = (*w).max(cell.text.width());
+            }
+        }
+        if let Some(total) = width {
+            let others: usize = widths
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != self.flexible)
+                .map(|(_, w)| w + 2)
+                .sum();
+            if let Some(w) = widths.get_mut(self.flexible) {
+                *w = (*w).min(
+                    total
+                        .saturating_sub(others)
+                        .max(self.header[self.flexible].width()),
+                );
+            }
+        }
+        let last = widths.len().saturating_sub(1);
+        let header = self
+            .header
+            .iter()
+            .zip(&widths)
+            .map(|(h, w)| pad(&h.to_uppercase(), *w))
+            .collect::<Vec<String>>()
+            .join("  ");
+        let header = header.trim_end();
+        let header = if color {
+            format!("\x1b[1m{}\x1b[0m", header)
+        } else {
+            header.to_string()
+        };
+        let rows = self.rows.iter().map(|row| {
+            row.iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(i, (cell, w))| {
+                    let text = truncate(&cell.text, *w);
+                    // trailing spaces are not needed after the last column
+                    let text = if i == last { text } else { pad(&text, *w) };
+                    paint(&text, cell.color, color)
+                })
+                .collect::<Vec<String>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        });
+        std::iter::once(header).chain(rows).collect()
+    }
+}
+
+/// Bar of actual pomodoros against estimate like `███░ 3/4`. Overrun is red.
+pub fn progress(actual: i64, estimate: i64) -> Cell {
+    let label = format!("{}/{}", actual, estimate);
+    let total = actual.max(estimate);
+    let color = if actual > estimate {
+        Some(Color::Red)
+    } else if actual > 0 && actual == estimate {
+        Some(Color::Green)
+    } else {
+        None
+    };
+    if total <= 0 || total > BAR_MAX {
+        return Cell::colored(label, color);
+    }
+    let done = actual.clamp(0, total) as usize;
+    let left = (total as usize).saturating_sub(done);
+    let bar = format!("{}{}", "█".repeat(done), "░".repeat(left));
+    Cell::colored(format!("{} {}", bar, label), color)
+}
+
+/// Priority colored by its position from the highest among `count` priorities.
+/// The highest is red, the next is yellow and the lowest is dimmed.
+pub fn priority(name: &str, position: usize, count: usize) -> Cell {
+    let color = match position {
+        0 => Some(Color::Red),
+        1 if count > 2 => Some(Color::Yellow),
+        p if count > 2 && p + 1 == count => Some(Color::Dim),
+        _ => None,
+    };
+    Cell::colored(name, color)
+}
+
+pub fn is_terminal() -> bool {
+    std::io::stdout().is_terminal()
+}
+
+/// Colors are disabled by `NO_COLOR` (https://no-color.org) or when stdout is not terminal.
+pub fn use_color() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && is_terminal()
+}
+
+/// Width of terminal attached to stdout, or `COLUMNS` when it is unknown.
+pub fn terminal_width() -> Option<usize> {
+    terminal_size::terminal_size()
+        .map(|(terminal_size::Width(w), _)| w as usize)
+        .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{priority, progress, truncate, Cell, Color, Table};
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("write report", 20), "write report");
+        assert_eq!(truncate("write report", 6), "write…");
+        assert_eq!(truncate("報告書を書く", 5), "報告…");
+    }
+
+    #[test]
+    fn test_progress() {
+        assert_eq!(progress(1, 3).text, "█░░ 1/3");
+        assert_eq!(progress(3, 2).text, "███ 3/2");
+        assert_eq!(progress(0, 0).text, "0/0");
+        assert_eq!(progress(2, 12).text, "2/12");
+    }
+
+    #[test]
+    fn test_priority_color() {
+        assert_eq!(priority("high", 0, 4).color, Some(Color::Red));
+        assert_eq!(priority("medium", 1, 4).color, Some(Color::Yellow));
+        assert_eq!(priority("low", 2, 4).color, None);
+        assert_eq!(priority("none", 3, 4).color, Some(Color::Dim));
+        assert_eq!(priority("low", 1, 2).color, None);
+    }
+
+    #[test]
+    fn test_render_fits_width() {
+        let table = Table {
+            header: vec!["id", "summary", "lane"],
+            rows: vec![vec![
+                Cell::plain("1"),
+                Cell::plain("write a very long report"),
+                Cell::plain("backlog"),
+            ]],
+            flexible: 1,
+        };
+        assert_eq!(
+            table.render(Some(24), false),
+            vec!["ID  SUMMARY      LANE", "1   write a ve…  backlog"]
+        );
+        assert_eq!(
+            table.render(None, false)[1],
+            "1   write a very long report  backlog"
+        );
+    }
+}
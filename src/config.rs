@@ -1,24 +1,323 @@
-use anyhow::Result;
-use chrono::FixedOffset;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::env;
-use std::path::PathBuf;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const KEY_DATABASE: &str = "database";
+const KEY_SHORT_BREAK: &str = "short_break";
+const KEY_LONG_BREAK: &str = "long_break";
+const KEY_LONG_BREAK_INTERVAL: &str = "long_break_interval";
+const KEY_POMODORO: &str = "pomodoro";
+const KEY_TIMEZONE: &str = "timezone";
+const KEY_ADDRESS: &str = "address";
+const KEY_PORT: &str = "port";
+const KEY_REMOTE: &str = "remote";
+
+/// Where the effective value of a config entry came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    Env,
+    Cli,
+}
+
+/// Set of values given by a single source. Unset values fall through to lower layer.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layer {
+    pub database: Option<PathBuf>,
+    pub short_break: Option<i64>,
+    pub long_break: Option<i64>,
+    pub long_break_interval: Option<usize>,
+    pub pomodoro: Option<i64>,
+    pub timezone: Option<String>,
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub remote: Option<String>,
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database: PathBuf,
     pub short_break: i64,
     pub long_break: i64,
-    pub timezone: FixedOffset,
+    /// Take long break after every this number of pomodoros
+    pub long_break_interval: usize,
+    pub pomodoro: i64,
+    pub timezone: Tz,
+    pub address: String,
+    pub port: u16,
+    /// URL of ly server to send commands to instead of opening local database
+    pub remote: Option<String>,
+    origins: BTreeMap<&'static str, Origin>,
 }
 
-impl Config {
+fn env_key(key: &str) -> String {
+    format!("LY_{}", key.to_uppercase())
+}
+
+fn parse_env<T>(key: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    <T as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    let name = env_key(key);
+    match env::var(&name) {
+        Ok(v) => {
+            let parsed = v
+                .parse::<T>()
+                .with_context(|| format!("Failed to parse {}={}", name, v))?;
+            Ok(Some(parsed))
+        }
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", name)),
+    }
+}
+
+/// Parses IANA timezone name like `Asia/Tokyo`, `Europe/Berlin` or `UTC`.
+pub fn parse_timezone(input: &str) -> Result<Tz> {
+    input
+        .parse::<Tz>()
+        .map_err(|e| anyhow!("{}: timezone must be IANA name like Asia/Tokyo", e))
+}
+
+/// Durations in minutes must be positive, otherwise timers expire as soon as started.
+fn ensure_positive(key: &str, value: i64, origin: &Origin) -> Result<i64> {
+    if value <= 0 {
+        bail!("{} must be positive, given {} by {}", key, value, origin);
+    }
+    Ok(value)
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(path) => write!(f, "file:{}", path.display()),
+            Origin::Env => write!(f, "env"),
+            Origin::Cli => write!(f, "cli"),
+        }
+    }
+}
+
+impl Layer {
     pub fn from_env() -> Result<Self> {
+        Ok(Layer {
+            database: parse_env(KEY_DATABASE)?,
+            short_break: parse_env(KEY_SHORT_BREAK)?,
+            long_break: parse_env(KEY_LONG_BREAK)?,
+            long_break_interval: parse_env(KEY_LONG_BREAK_INTERVAL)?,
+            pomodoro: parse_env(KEY_POMODORO)?,
+            timezone: parse_env(KEY_TIMEZONE)?,
+            address: parse_env(KEY_ADDRESS)?,
+            port: parse_env(KEY_PORT)?,
+            remote: parse_env(KEY_REMOTE)?,
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+}
+
+/// Default location of config file: `$XDG_CONFIG_HOME/ly/config.toml` or `$HOME/.config/ly/config.toml`.
+pub fn default_path() -> Result<PathBuf> {
+    let base = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var("HOME")?).join(".config"),
+    };
+    Ok(base.join("ly").join("config.toml"))
+}
+
+impl Config {
+    fn defaults() -> Result<Self> {
         let home = env::var("HOME")?;
         Ok(Config {
             database: PathBuf::from(format!("{}/.ly.db", home)),
             short_break: 5,
             long_break: 15,
-            timezone: FixedOffset::east(9 * 3600),
+            long_break_interval: 4,
+            pomodoro: 25,
+            timezone: Tz::Asia__Tokyo,
+            address: String::from("0.0.0.0"),
+            port: 8080,
+            remote: None,
+            origins: BTreeMap::new(),
         })
     }
+
+    /// Merges defaults, config file, `LY_*` environment variables and CLI overrides in this order.
+    /// Config file path is taken from `config_path`, `LY_CONFIG` or [`default_path`].
+    pub fn load(config_path: Option<&Path>, cli: Layer) -> Result<Self> {
+        let mut conf = Config::defaults()?;
+        let explicit = config_path
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("LY_CONFIG").map(PathBuf::from));
+        let path = match explicit {
+            Some(path) => Some(path),
+            None => Some(default_path()?).filter(|p| p.exists()),
+        };
+        if let Some(path) = path {
+            debug!("loading config file {}", path.display());
+            conf.apply(Layer::from_file(&path)?, Origin::File(path))?;
+        }
+        conf.apply(Layer::from_env()?, Origin::Env)?;
+        conf.apply(cli, Origin::Cli)?;
+        Ok(conf)
+    }
+
+    fn set_origin(&mut self, key: &'static str, origin: &Origin) {
+        self.origins.insert(key, origin.clone());
+    }
+
+    fn apply(&mut self, layer: Layer, origin: Origin) -> Result<()> {
+        if let Some(v) = layer.database {
+            self.database = v;
+            self.set_origin(KEY_DATABASE, &origin);
+        }
+        if let Some(v) = layer.short_break {
+            self.short_break = ensure_positive(KEY_SHORT_BREAK, v, &origin)?;
+            self.set_origin(KEY_SHORT_BREAK, &origin);
+        }
+        if let Some(v) = layer.long_break {
+            self.long_break = ensure_positive(KEY_LONG_BREAK, v, &origin)?;
+            self.set_origin(KEY_LONG_BREAK, &origin);
+        }
+        if let Some(v) = layer.long_break_interval {
+            if v == 0 {
+                bail!(
+                    "{} must be positive, given by {}",
+                    KEY_LONG_BREAK_INTERVAL,
+                    origin
+                );
+            }
+            self.long_break_interval = v;
+            self.set_origin(KEY_LONG_BREAK_INTERVAL, &origin);
+        }
+        if let Some(v) = layer.pomodoro {
+            self.pomodoro = ensure_positive(KEY_POMODORO, v, &origin)?;
+            self.set_origin(KEY_POMODORO, &origin);
+        }
+        if let Some(v) = layer.timezone {
+            self.timezone = parse_timezone(&v)
+                .with_context(|| format!("Invalid timezone given by {}", origin))?;
+            self.set_origin(KEY_TIMEZONE, &origin);
+        }
+        if let Some(v) = layer.address {
+            self.address = v;
+            self.set_origin(KEY_ADDRESS, &origin);
+        }
+        if let Some(v) = layer.port {
+            self.port = v;
+            self.set_origin(KEY_PORT, &origin);
+        }
+        if let Some(v) = layer.remote {
+            self.remote = Some(v);
+            self.set_origin(KEY_REMOTE, &origin);
+        }
+        Ok(())
+    }
+
+    /// Returns where the value of `key` came from.
+    pub fn origin(&self, key: &str) -> Origin {
+        self.origins.get(key).cloned().unwrap_or(Origin::Default)
+    }
+
+    /// Effective values as (key, value, origin) for display.
+    pub fn entries(&self) -> Vec<(&'static str, String, String)> {
+        let values = [
+            (KEY_DATABASE, self.database.display().to_string()),
+            (KEY_SHORT_BREAK, self.short_break.to_string()),
+            (KEY_LONG_BREAK, self.long_break.to_string()),
+            (
+                KEY_LONG_BREAK_INTERVAL,
+                self.long_break_interval.to_string(),
+            ),
+            (KEY_POMODORO, self.pomodoro.to_string()),
+            (KEY_TIMEZONE, self.timezone.name().to_string()),
+            (KEY_ADDRESS, self.address.clone()),
+            (KEY_PORT, self.port.to_string()),
+            (KEY_REMOTE, self.remote.clone().unwrap_or_default()),
+        ];
+        values
+            .into_iter()
+            .map(|(key, value)| {
+                let origin = match self.origin(key) {
+                    Origin::Env => format!("env:{}", env_key(key)),
+                    o => o.to_string(),
+                };
+                (key, value, origin)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_timezone, Config, Layer, Origin};
+    use anyhow::Result;
+    use chrono_tz::Tz;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_timezone() -> Result<()> {
+        assert_eq!(parse_timezone("Asia/Tokyo")?, Tz::Asia__Tokyo);
+        assert_eq!(parse_timezone("Europe/Berlin")?, Tz::Europe__Berlin);
+        assert_eq!(parse_timezone("UTC")?, Tz::UTC);
+        assert!(parse_timezone("+09:00").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_layers() -> Result<()> {
+        let mut conf = Config::defaults()?;
+        let path = PathBuf::from("config.toml");
+        let file: Layer =
+            toml::from_str("short_break = 10\ntimezone = \"Europe/Berlin\"\nport = 9000")?;
+        conf.apply(file, Origin::File(path.clone()))?;
+        let cli = Layer {
+            port: Some(9090),
+            ..Layer::default()
+        };
+        conf.apply(cli, Origin::Cli)?;
+        assert_eq!(conf.short_break, 10);
+        assert_eq!(conf.origin("short_break"), Origin::File(path));
+        assert_eq!(conf.timezone, Tz::Europe__Berlin);
+        assert_eq!(conf.port, 9090);
+        assert_eq!(conf.origin("port"), Origin::Cli);
+        assert_eq!(conf.long_break, 15);
+        assert_eq!(conf.origin("long_break"), Origin::Default);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_non_positive_duration() -> Result<()> {
+        let mut conf = Config::defaults()?;
+        let zero = Layer {
+            pomodoro: Some(0),
+            ..Layer::default()
+        };
+        let e = conf.apply(zero, Origin::Env).unwrap_err();
+        assert_eq!(e.to_string(), "pomodoro must be positive, given 0 by env");
+        let file: Layer = toml::from_str("long_break = -5")?;
+        assert!(conf
+            .apply(file, Origin::File(PathBuf::from("config.toml")))
+            .is_err());
+        assert_eq!(conf.pomodoro, 25);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_unknown_key() {
+        let parsed: Result<Layer, _> = toml::from_str("short_brake = 10");
+        assert!(parsed.is_err());
+    }
 }
@@ -0,0 +1,47 @@
+use super::common::Id;
+use super::timer::TimerType;
+use super::todo;
+use anyhow::Result;
+use chrono::serde::ts_milliseconds;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Break taken between pomodoros.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Break {
+    pub id: Id,
+    pub timer_type: TimerType,
+    #[serde(with = "ts_milliseconds")]
+    pub started_at: DateTime<Utc>,
+    #[serde(with = "ts_milliseconds")]
+    pub finished_at: DateTime<Utc>,
+    /// Planned length of the break
+    pub duration_min: i64,
+    /// Seconds spent in pauses between start and finish
+    pub paused_sec: i64,
+}
+
+impl Break {
+    /// Minutes actually rested excluding pauses, which can be shorter than planned.
+    pub fn rested_min(&self) -> i64 {
+        ((self.finished_at - self.started_at).num_seconds() - self.paused_sec) / 60
+    }
+}
+
+pub trait Fetch {
+    fn fetch_breaks_between(
+        &mut self,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Result<Vec<Break>>;
+}
+
+/// Lists breaks started on the day starting at `date` in `timezone`.
+pub fn list_on_date<R, Tz>(r: &mut R, date: &todo::TodoDate, timezone: &Tz) -> Result<Vec<Break>>
+where
+    R: Fetch,
+    Tz: TimeZone,
+{
+    let end = todo::end_of_day_in_tz(date, timezone);
+    r.fetch_breaks_between(date, &end)
+}
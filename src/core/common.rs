@@ -1,17 +1,95 @@
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result};
 /// Autoincrement integer
 pub type Id = i64;
 
-#[derive(Debug, Clone)]
+/// Failure which callers may want to tell apart from others,
+/// like exit code of CLI or status code of web API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "target", rename_all = "snake_case")]
 pub enum RepositoryError {
-    NotFound,
+    TaskNotFound(Id),
+    LaneNotFound(String),
+    PriorityNotFound(String),
+    TagNotFound(String),
+    /// Task is not planned on the todo
+    TaskNotPlanned(Id),
+    TaskArchived(Id),
+    TaskNotArchived(Id),
+    /// Task is bound to current timer
+    TaskOnTimer(Id),
+    TimerNotRunning,
+    /// Running timer is not bound to any task
+    TimerTaskNotFound,
+    TimerAlreadyRunning,
+    /// Timer was stopped by other process meanwhile
+    TimerAlreadyStopped,
+    TimerPaused,
+    TimerNotPaused,
+    /// Pomodoro is completed only at its deadline
+    PomodoroNotFinished,
+    LaneAlreadyExists(String),
+    PriorityAlreadyExists(String),
+    TagAlreadyExists(String),
+    /// Lane is listed more than once to reorder
+    LaneListedTwice(String),
+    /// Priority is listed more than once to reorder
+    PriorityListedTwice(String),
+}
+
+impl RepositoryError {
+    /// Referred entity does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            RepositoryError::TaskNotFound(_)
+                | RepositoryError::LaneNotFound(_)
+                | RepositoryError::PriorityNotFound(_)
+                | RepositoryError::TagNotFound(_)
+                | RepositoryError::TaskNotPlanned(_)
+                | RepositoryError::TimerNotRunning
+                | RepositoryError::TimerTaskNotFound
+        )
+    }
 }
 
 impl Display for RepositoryError {
     fn fmt(&self, f: &mut Formatter) -> Result {
         match self {
-            RepositoryError::NotFound => write!(f, "Entity not found"),
+            RepositoryError::TaskNotFound(id) => write!(f, "task not found: {}", id),
+            RepositoryError::LaneNotFound(name) => write!(f, "lane not found: {}", name),
+            RepositoryError::PriorityNotFound(name) => write!(f, "priority not found: {}", name),
+            RepositoryError::TagNotFound(name) => write!(f, "tag not found: {}", name),
+            RepositoryError::TaskNotPlanned(id) => write!(f, "task {} is not planned", id),
+            RepositoryError::TaskArchived(id) => write!(f, "task {} is archived", id),
+            RepositoryError::TaskNotArchived(id) => write!(f, "task {} is not archived", id),
+            RepositoryError::TaskOnTimer(id) => write!(
+                f,
+                "task {} is running on current timer. complete the timer before removing it",
+                id
+            ),
+            RepositoryError::TimerNotRunning => write!(f, "no timer is running"),
+            RepositoryError::TimerTaskNotFound => write!(f, "no task is bound to current timer"),
+            RepositoryError::TimerAlreadyRunning => write!(f, "Timer is already running"),
+            RepositoryError::TimerAlreadyStopped => write!(f, "timer was already stopped"),
+            RepositoryError::TimerPaused => write!(f, "timer is already paused"),
+            RepositoryError::TimerNotPaused => write!(f, "timer is not paused"),
+            RepositoryError::PomodoroNotFinished => write!(
+                f,
+                "pomodoro has not reached its deadline. void or cancel it to stop early"
+            ),
+            RepositoryError::LaneAlreadyExists(name) => write!(f, "lane already exists: {}", name),
+            RepositoryError::PriorityAlreadyExists(name) => {
+                write!(f, "priority already exists: {}", name)
+            }
+            RepositoryError::TagAlreadyExists(name) => write!(f, "tag already exists: {}", name),
+            RepositoryError::LaneListedTwice(name) => {
+                write!(f, "lane is listed more than once: {}", name)
+            }
+            RepositoryError::PriorityListedTwice(name) => {
+                write!(f, "priority is listed more than once: {}", name)
+            }
         }
     }
 }
@@ -0,0 +1,76 @@
+use super::common::{Id, RepositoryError};
+use super::pomodoro;
+use super::task;
+use anyhow::Result;
+use chrono::serde::ts_milliseconds;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// Estimated pomodoro count of task at some point.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Estimate {
+    pub id: Id,
+    pub task_id: Id,
+    pub value: i64,
+    #[serde(with = "ts_milliseconds")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Comparison of estimates with actual pomodoro count of a task.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Accuracy {
+    pub task_id: Id,
+    pub summary: String,
+    pub first: i64,
+    pub last: i64,
+    pub actual: i64,
+    pub revisions: usize,
+}
+
+impl Accuracy {
+    /// Compares actual count with the first estimate.
+    pub fn outcome(&self) -> Ordering {
+        self.actual.cmp(&self.first)
+    }
+}
+
+pub trait Fetch {
+    fn fetch_estimates_by_task_id(&mut self, task_id: Id) -> Result<Vec<Estimate>>;
+}
+
+pub fn list_estimates<R>(r: &mut R, task_id: Id) -> Result<Vec<Estimate>>
+where
+    R: Fetch + task::Fetch,
+{
+    let task = r
+        .fetch_task_by_id(task_id)?
+        .ok_or(RepositoryError::TaskNotFound(task_id))?;
+    r.fetch_estimates_by_task_id(task.id)
+}
+
+/// Builds accuracy of estimates for tasks in the lane.
+pub fn accuracy_report<R>(r: &mut R, lane_name: &str) -> Result<Vec<Accuracy>>
+where
+    R: Fetch + task::Fetch + pomodoro::Fetch,
+{
+    let tasks = r.fetch_all_tasks(lane_name)?;
+    let mut report = Vec::with_capacity(tasks.len());
+    for t in tasks {
+        let estimates = r.fetch_estimates_by_task_id(t.id)?;
+        let actual = r
+            .fetch_by_task_id(t.id)?
+            .iter()
+            .filter(|p| p.is_completed())
+            .count() as i64;
+        report.push(Accuracy {
+            task_id: t.id,
+            summary: t.summary,
+            first: estimates.first().map(|e| e.value).unwrap_or(t.estimate),
+            last: t.estimate,
+            actual,
+            revisions: estimates.len().saturating_sub(1),
+        });
+    }
+    Ok(report)
+}
@@ -0,0 +1,37 @@
+use super::timer::Timer;
+use super::todo::TodoDate;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Change of state notified to subscribers like browser.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Event {
+    TimerStarted(Timer),
+    TimerPaused(Timer),
+    TimerResumed(Timer),
+    TimerCompleted(Timer),
+    TimerVoided(Timer),
+    TimerCancelled(Timer),
+    TodoChanged { date: TodoDate },
+}
+
+impl Event {
+    /// Name used to tell kind of event apart, e.g. `timer_started`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::TimerStarted(_) => "timer_started",
+            Event::TimerPaused(_) => "timer_paused",
+            Event::TimerResumed(_) => "timer_resumed",
+            Event::TimerCompleted(_) => "timer_completed",
+            Event::TimerVoided(_) => "timer_voided",
+            Event::TimerCancelled(_) => "timer_cancelled",
+            Event::TodoChanged { .. } => "todo_changed",
+        }
+    }
+}
+
+pub trait Publish {
+    /// Having no subscriber is not an error.
+    fn publish(&mut self, event: &Event) -> Result<()>;
+}
@@ -0,0 +1,71 @@
+use super::common::{Id, RepositoryError};
+use super::timer;
+use super::todo;
+use anyhow::Result;
+use chrono::serde::ts_milliseconds;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Interruption occurred during pomodoro.
+/// Internal one comes from ourselves, external one from other people.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Interruption {
+    pub id: Id,
+    pub task_id: Id,
+    pub external: bool,
+    pub note: Option<String>,
+    #[serde(with = "ts_milliseconds")]
+    pub created_at: DateTime<Utc>,
+}
+
+pub trait Add {
+    fn add_interruption(
+        &mut self,
+        task_id: Id,
+        external: bool,
+        note: Option<&str>,
+        created_at: DateTime<Utc>,
+    ) -> Result<()>;
+}
+
+pub trait Fetch {
+    fn fetch_interruptions_by_task_id(&mut self, task_id: Id) -> Result<Vec<Interruption>>;
+    fn fetch_interruptions_between(
+        &mut self,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Result<Vec<Interruption>>;
+}
+
+/// Records interruption on the task of current pomodoro.
+pub fn interrupt<R>(r: &mut R, external: bool, note: Option<&str>) -> Result<Id>
+where
+    R: Add + timer::TimerTaskGet,
+{
+    let timer_task = r
+        .get_timer_task()?
+        .ok_or(RepositoryError::TimerNotRunning)?;
+    r.add_interruption(timer_task.task_id, external, note, Utc::now())?;
+    Ok(timer_task.task_id)
+}
+
+pub fn list_by_task<R>(r: &mut R, task_id: Id) -> Result<Vec<Interruption>>
+where
+    R: Fetch,
+{
+    r.fetch_interruptions_by_task_id(task_id)
+}
+
+/// Lists interruptions on the day starting at `date` in `timezone`.
+pub fn list_on_date<R, Tz>(
+    r: &mut R,
+    date: &todo::TodoDate,
+    timezone: &Tz,
+) -> Result<Vec<Interruption>>
+where
+    R: Fetch,
+    Tz: TimeZone,
+{
+    let end = todo::end_of_day_in_tz(date, timezone);
+    r.fetch_interruptions_between(date, &end)
+}
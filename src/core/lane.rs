@@ -1,28 +1,156 @@
-use super::common::Id;
-use anyhow::Result;
+use super::common::{Id, RepositoryError};
+use super::task;
+use anyhow::{bail, Result};
 use chrono::serde::ts_milliseconds;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 /// Lane of task list.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Lane {
     pub id: Id,
     pub name: String,
+    /// Position in the process. Lanes are listed in ascending order.
+    pub lane_order: i64,
     #[serde(with = "ts_milliseconds")]
     pub created_at: DateTime<Utc>,
     #[serde(with = "ts_milliseconds")]
     pub updated_at: DateTime<Utc>,
 }
 
+pub trait Add {
+    fn add_lane(&mut self, name: &str, lane_order: i64) -> Result<Id>;
+}
+
 pub trait Fetch {
     fn fetch_lane_by_name(&mut self, name: &str) -> Result<Option<Lane>>;
     fn fetch_all_lanes(&mut self) -> Result<Vec<Lane>>;
 }
 
+pub trait Mod {
+    fn rename_lane(&mut self, id: Id, name: &str) -> Result<()>;
+    /// Sets every `(id, lane_order)` pair at once.
+    fn mod_lane_orders(&mut self, orders: &[(Id, i64)]) -> Result<()>;
+}
+
+pub trait Remove {
+    /// Deletes lane. Its tasks including archived ones are moved to `move_to` beforehand.
+    fn remove_lane(&mut self, id: Id, move_to: Option<Id>) -> Result<()>;
+}
+
+/// Resolves lane name given by user.
+pub fn fetch_existing_lane<R>(r: &mut R, name: &str) -> Result<Lane>
+where
+    R: Fetch,
+{
+    r.fetch_lane_by_name(name)?
+        .ok_or_else(|| RepositoryError::LaneNotFound(name.to_string()).into())
+}
+
+fn validate_new_name<R>(r: &mut R, name: &str) -> Result<()>
+where
+    R: Fetch,
+{
+    if name.trim().is_empty() || name.trim() != name {
+        bail!(
+            "lane name must not be empty nor surrounded by spaces: {:?}",
+            name
+        );
+    }
+    if r.fetch_lane_by_name(name)?.is_some() {
+        return Err(RepositoryError::LaneAlreadyExists(name.to_string()).into());
+    }
+    Ok(())
+}
+
 pub fn fetch_all_lanes<R>(r: &mut R) -> Result<Vec<Lane>>
 where
     R: Fetch,
 {
     r.fetch_all_lanes()
 }
+
+/// Adds lane at the end of the process.
+pub fn add_lane<R>(r: &mut R, name: &str) -> Result<Id>
+where
+    R: Add + Fetch,
+{
+    validate_new_name(r, name)?;
+    let last = r
+        .fetch_all_lanes()?
+        .iter()
+        .map(|l| l.lane_order)
+        .max()
+        .unwrap_or(0);
+    r.add_lane(name, last + 1)
+}
+
+pub fn rename_lane<R>(r: &mut R, name: &str, new_name: &str) -> Result<()>
+where
+    R: Fetch + Mod,
+{
+    let lane = fetch_existing_lane(r, name)?;
+    validate_new_name(r, new_name)?;
+    r.rename_lane(lane.id, new_name)
+}
+
+/// Orders lanes as listed in `names`, which must contain every lane exactly once.
+pub fn reorder_lanes<R>(r: &mut R, names: &[String]) -> Result<()>
+where
+    R: Fetch + Mod,
+{
+    let lanes = r.fetch_all_lanes()?;
+    let mut orders = Vec::with_capacity(names.len());
+    let mut seen = HashSet::new();
+    for name in names {
+        let lane = lanes
+            .iter()
+            .find(|l| &l.name == name)
+            .ok_or_else(|| RepositoryError::LaneNotFound(name.clone()))?;
+        if !seen.insert(lane.id) {
+            return Err(RepositoryError::LaneListedTwice(name.clone()).into());
+        }
+        orders.push((lane.id, orders.len() as i64 + 1));
+    }
+    let missing: Vec<&str> = lanes
+        .iter()
+        .filter(|l| !seen.contains(&l.id))
+        .map(|l| l.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        bail!("every lane must be listed, missing: {}", missing.join(", "));
+    }
+    r.mod_lane_orders(&orders)
+}
+
+/// Removes lane. Lane which still has tasks is removed only when `move_to` is given,
+/// so that no task is left without lane.
+pub fn remove_lane<R>(r: &mut R, name: &str, move_to: Option<&str>) -> Result<()>
+where
+    R: Fetch + Remove + task::Fetch,
+{
+    let lane = fetch_existing_lane(r, name)?;
+    let target = match move_to {
+        Some(to) if to == name => bail!("cannot move tasks to the lane being removed"),
+        Some(to) => Some(fetch_existing_lane(r, to)?.id),
+        None => None,
+    };
+    if target.is_none() {
+        let active = r.fetch_all_tasks(name)?.len();
+        let archived = r
+            .fetch_archived_tasks()?
+            .iter()
+            .filter(|t| t.lane_id == lane.id)
+            .count();
+        if active + archived > 0 {
+            bail!(
+                "lane {} still has {} tasks ({} archived); specify lane to move them to",
+                name,
+                active + archived,
+                archived
+            );
+        }
+    }
+    r.remove_lane(lane.id, target)
+}
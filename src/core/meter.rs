@@ -1,20 +1,27 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
 
 use anyhow::Result;
 use chrono::serde::ts_milliseconds;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
 use serde::{ser::SerializeTuple, Deserialize, Serialize, Serializer};
 
+// variants follow instrument names like "pomodoro.daily"
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Instrument {
     PomodoroDaily,
+    InterruptionDaily,
+    BreakDaily,
 }
 
 impl Display for Instrument {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
-            &Self::PomodoroDaily => "pomodoro.daily",
+        let s = match *self {
+            Self::PomodoroDaily => "pomodoro.daily",
+            Self::InterruptionDaily => "interruption.daily",
+            Self::BreakDaily => "break.daily",
         };
         write!(f, "{}", s)
     }
@@ -66,8 +73,36 @@ pub struct TimeRange {
     pub end: DateTime<Utc>,
 }
 
+/// Sums values per local date in `timezone`.
+/// Each measurement is labelled with the date as midnight in UTC regardless of timezone.
+pub fn sum_daily<Z: TimeZone>(values: &[(DateTime<Utc>, f64)], timezone: &Z) -> Vec<Measurement> {
+    let mut sums: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+    for (t, v) in values {
+        let date = t.with_timezone(timezone).naive_local().date();
+        *sums.entry(date).or_insert(0.0) += v;
+    }
+    sums.into_iter()
+        .map(|(date, sum)| Measurement::new(Utc.from_utc_date(&date).and_hms(0, 0, 0), sum))
+        .collect()
+}
+
+/// Counts timestamps per local date in `timezone`.
+pub fn count_daily<Z: TimeZone>(times: &[DateTime<Utc>], timezone: &Z) -> Vec<Measurement> {
+    let values: Vec<(DateTime<Utc>, f64)> = times.iter().map(|t| (*t, 1.0)).collect();
+    sum_daily(&values, timezone)
+}
+
 pub trait MeterQuery {
-    fn query_pomodoro_daily(&mut self, range: &TimeRange) -> Result<Measurements>;
+    fn query_pomodoro_daily(&mut self, range: &TimeRange, timezone: &Tz) -> Result<Measurements>;
+    /// Daily count of either external or internal interruptions. Labelled with `external`.
+    fn query_interruption_daily(
+        &mut self,
+        range: &TimeRange,
+        timezone: &Tz,
+        external: bool,
+    ) -> Result<Measurements>;
+    /// Daily sum of minutes actually rested in breaks.
+    fn query_break_daily(&mut self, range: &TimeRange, timezone: &Tz) -> Result<Measurements>;
 }
 
 #[cfg(test)]
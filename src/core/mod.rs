@@ -1,10 +1,81 @@
+pub mod breaks;
 mod common;
+pub mod estimate;
+pub mod event;
+pub mod interruption;
 pub mod lane;
 pub mod meter;
 pub mod pomodoro;
 pub mod priority;
+pub mod tag;
 pub mod task;
 pub mod timer;
 pub mod todo;
 
-pub use self::common::Id;
+pub use self::common::{Id, RepositoryError};
+
+/// Every operation on stored data used by commands.
+/// Implemented by local database session.
+pub trait Repository:
+    breaks::Fetch
+    + estimate::Fetch
+    + event::Publish
+    + interruption::Add
+    + interruption::Fetch
+    + lane::Add
+    + lane::Fetch
+    + lane::Mod
+    + lane::Remove
+    + pomodoro::Fetch
+    + priority::Add
+    + priority::Fetch
+    + priority::Mod
+    + tag::Add
+    + tag::Fetch
+    + tag::Remove
+    + tag::Attach
+    + tag::Detach
+    + task::Add
+    + task::Fetch
+    + task::Mod
+    + task::Remove
+    + timer::TimerTaskGet
+    + timer::Lifecycle
+    + timer::Get
+    + todo::Add
+    + todo::Fetch
+    + todo::Mod
+{
+}
+
+impl<R> Repository for R where
+    R: breaks::Fetch
+        + estimate::Fetch
+        + event::Publish
+        + interruption::Add
+        + interruption::Fetch
+        + lane::Add
+        + lane::Fetch
+        + lane::Mod
+        + lane::Remove
+        + pomodoro::Fetch
+        + priority::Add
+        + priority::Fetch
+        + priority::Mod
+        + tag::Add
+        + tag::Fetch
+        + tag::Remove
+        + tag::Attach
+        + tag::Detach
+        + task::Add
+        + task::Fetch
+        + task::Mod
+        + task::Remove
+        + timer::TimerTaskGet
+        + timer::Lifecycle
+        + timer::Get
+        + todo::Add
+        + todo::Fetch
+        + todo::Mod
+{
+}
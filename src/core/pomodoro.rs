@@ -3,6 +3,70 @@ use anyhow::Result;
 use chrono::serde::ts_milliseconds;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+const OUTCOME_COMPLETED: u8 = 0;
+const OUTCOME_VOIDED: u8 = 1;
+const OUTCOME_CANCELLED: u8 = 2;
+
+/// How a timer ended.
+/// Voided pomodoro was interrupted and abandoned, so it does not count as done.
+/// Cancelled one was started by mistake and is not worth recording.
+/// Only completed break is recorded as rest.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Completed,
+    Voided,
+    Cancelled,
+}
+
+#[derive(Debug)]
+pub struct OutcomeFromIntError {
+    value: u8,
+}
+
+impl Display for OutcomeFromIntError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Failed to convert Outcome from {}", self.value)
+    }
+}
+
+impl std::error::Error for OutcomeFromIntError {}
+
+impl From<Outcome> for u8 {
+    fn from(val: Outcome) -> Self {
+        match val {
+            Outcome::Completed => OUTCOME_COMPLETED,
+            Outcome::Voided => OUTCOME_VOIDED,
+            Outcome::Cancelled => OUTCOME_CANCELLED,
+        }
+    }
+}
+
+impl TryFrom<u8> for Outcome {
+    type Error = OutcomeFromIntError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            OUTCOME_COMPLETED => Ok(Outcome::Completed),
+            OUTCOME_VOIDED => Ok(Outcome::Voided),
+            OUTCOME_CANCELLED => Ok(Outcome::Cancelled),
+            _ => Err(OutcomeFromIntError { value }),
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Outcome::Completed => write!(f, "completed"),
+            Outcome::Voided => write!(f, "voided"),
+            Outcome::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Pomodoro {
@@ -12,13 +76,46 @@ pub struct Pomodoro {
     pub started_at: DateTime<Utc>,
     #[serde(with = "ts_milliseconds")]
     pub finished_at: DateTime<Utc>,
+    pub outcome: Outcome,
+    /// Seconds spent in pauses between start and finish
+    pub paused_sec: i64,
 }
 
-pub trait Complete {
-    // TODO: add finished_at to create pomodoro manually
-    fn complete_pomodoro(&mut self, task_id: Id, started_at: DateTime<Utc>) -> Result<()>;
+impl Pomodoro {
+    /// Only completed pomodoro counts as actual work.
+    pub fn is_completed(&self) -> bool {
+        self.outcome == Outcome::Completed
+    }
+
+    /// Seconds worked on the pomodoro, excluding pauses.
+    pub fn spent_sec(&self) -> i64 {
+        (self.finished_at - self.started_at).num_seconds() - self.paused_sec
+    }
 }
 
 pub trait Fetch {
     fn fetch_by_task_id(&mut self, task_id: Id) -> Result<Vec<Pomodoro>>;
+    fn fetch_pomodoros_between(
+        &mut self,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Result<Vec<Pomodoro>>;
+    /// Returns number of completed pomodoros by task. Tasks without one are left out.
+    fn fetch_completed_counts(&mut self) -> Result<HashMap<Id, usize>>;
+}
+
+/// Pomodoros recorded on the task in the order they were started.
+pub fn list_by_task<R>(r: &mut R, task_id: Id) -> Result<Vec<Pomodoro>>
+where
+    R: Fetch,
+{
+    r.fetch_by_task_id(task_id)
+}
+
+/// Counts completed pomodoros of every task at once.
+pub fn count_completed<R>(r: &mut R) -> Result<HashMap<Id, usize>>
+where
+    R: Fetch,
+{
+    r.fetch_completed_counts()
 }
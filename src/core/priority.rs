@@ -1,28 +1,156 @@
-use super::common::Id;
-use anyhow::Result;
+use super::common::{Id, RepositoryError};
+use anyhow::{bail, Result};
 use chrono::serde::ts_milliseconds;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
-/// Task to be done.
+/// Urgency of task. Tasks of higher rank come first.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Priority {
     pub id: Id,
     pub name: String,
+    /// Short name accepted in place of the name, like `h` for `high`
+    pub alias: Option<String>,
+    pub rank: i64,
     #[serde(with = "ts_milliseconds")]
     pub created_at: DateTime<Utc>,
     #[serde(with = "ts_milliseconds")]
     pub updated_at: DateTime<Utc>,
 }
 
+pub trait Add {
+    fn add_priority(&mut self, name: &str, alias: Option<&str>, rank: i64) -> Result<Id>;
+}
+
 pub trait Fetch {
-    fn fetch_priority_by_name(&mut self, name: &str) -> Result<Priority>;
+    /// Finds priority by its name or alias.
+    fn fetch_priority_by_name(&mut self, name: &str) -> Result<Option<Priority>>;
+    /// Lists priorities from the highest rank.
     fn fetch_all_priority(&mut self) -> Result<Vec<Priority>>;
 }
 
+pub trait Mod {
+    fn rename_priority(&mut self, id: Id, name: &str, alias: Option<&str>) -> Result<()>;
+    /// Sets every `(id, rank)` pair at once.
+    fn mod_priority_ranks(&mut self, ranks: &[(Id, i64)]) -> Result<()>;
+}
+
 pub fn fetch_all_priority<R>(r: &mut R) -> Result<Vec<Priority>>
 where
     R: Fetch,
 {
     r.fetch_all_priority()
 }
+
+/// Resolves name or alias given by user.
+pub fn fetch_existing_priority<R>(r: &mut R, name: &str) -> Result<Priority>
+where
+    R: Fetch,
+{
+    r.fetch_priority_by_name(name)?
+        .ok_or_else(|| RepositoryError::PriorityNotFound(name.to_string()).into())
+}
+
+/// Names and aliases share one namespace so that either resolves to one priority.
+fn validate_new_names<R>(r: &mut R, except: Option<Id>, names: &[&str]) -> Result<()>
+where
+    R: Fetch,
+{
+    let others: Vec<Priority> = r
+        .fetch_all_priority()?
+        .into_iter()
+        .filter(|p| Some(p.id) != except)
+        .collect();
+    for name in names {
+        if name.trim().is_empty() || name.trim() != *name {
+            bail!(
+                "priority name must not be empty nor surrounded by spaces: {:?}",
+                name
+            );
+        }
+        if others
+            .iter()
+            .any(|p| p.name == *name || p.alias.as_deref() == Some(name))
+        {
+            return Err(RepositoryError::PriorityAlreadyExists(name.to_string()).into());
+        }
+    }
+    if names.len() > 1 && names[0] == names[1] {
+        bail!("alias must differ from name: {}", names[0]);
+    }
+    Ok(())
+}
+
+/// Adds priority above all existing ones.
+pub fn add_priority<R>(r: &mut R, name: &str, alias: Option<&str>) -> Result<Id>
+where
+    R: Add + Fetch,
+{
+    let names: Vec<&str> = std::iter::once(name).chain(alias).collect();
+    validate_new_names(r, None, &names)?;
+    let top = r
+        .fetch_all_priority()?
+        .iter()
+        .map(|p| p.rank)
+        .max()
+        .unwrap_or(0);
+    r.add_priority(name, alias, top + 1)
+}
+
+/// Changes name and/or alias. Omitted one is kept.
+pub fn rename_priority<R>(
+    r: &mut R,
+    name: &str,
+    new_name: Option<&str>,
+    alias: Option<&str>,
+) -> Result<()>
+where
+    R: Fetch + Mod,
+{
+    let prio = fetch_existing_priority(r, name)?;
+    if new_name.is_none() && alias.is_none() {
+        bail!("give new name or alias of priority {}", prio.name);
+    }
+    let new_name = new_name.unwrap_or(&prio.name);
+    let alias = alias.or(prio.alias.as_deref());
+    let names: Vec<&str> = std::iter::once(new_name).chain(alias).collect();
+    validate_new_names(r, Some(prio.id), &names)?;
+    r.rename_priority(prio.id, new_name, alias)
+}
+
+/// Ranks priorities as listed in `names` from the highest.
+/// Every priority must be listed exactly once by name or alias.
+pub fn reorder_priorities<R>(r: &mut R, names: &[String]) -> Result<()>
+where
+    R: Fetch + Mod,
+{
+    let all = r.fetch_all_priority()?;
+    let mut ids = Vec::with_capacity(names.len());
+    let mut seen = HashSet::new();
+    for name in names {
+        let prio = fetch_existing_priority(r, name)?;
+        if !seen.insert(prio.id) {
+            return Err(RepositoryError::PriorityListedTwice(prio.name).into());
+        }
+        ids.push(prio.id);
+    }
+    let missing: Vec<&str> = all
+        .iter()
+        .filter(|p| !seen.contains(&p.id))
+        .map(|p| p.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "every priority must be listed, missing: {}",
+            missing.join(", ")
+        );
+    }
+    let count = ids.len() as i64;
+    let ranks: Vec<(Id, i64)> = ids
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| (id, count - i as i64))
+        .collect();
+    r.mod_priority_ranks(&ranks)
+}
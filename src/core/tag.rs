@@ -0,0 +1,129 @@
+use super::common::{Id, RepositoryError};
+use super::task;
+use anyhow::Result;
+use chrono::serde::ts_milliseconds;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Label to classify tasks across lanes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: Id,
+    pub name: String,
+    #[serde(with = "ts_milliseconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "ts_milliseconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Tag attached to task.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskTag {
+    pub task_id: Id,
+    pub tag_id: Id,
+    pub name: String,
+}
+
+pub trait Add {
+    fn add_tag(&mut self, name: &str) -> Result<()>;
+}
+
+pub trait Fetch {
+    fn fetch_tag_by_name(&mut self, name: &str) -> Result<Option<Tag>>;
+    fn fetch_all_tags(&mut self) -> Result<Vec<Tag>>;
+    fn fetch_all_task_tags(&mut self) -> Result<Vec<TaskTag>>;
+}
+
+pub trait Remove {
+    /// Deletes tag and detaches it from all tasks.
+    fn remove_tag(&mut self, id: Id) -> Result<()>;
+}
+
+pub trait Attach {
+    fn attach_tag(&mut self, tag_id: Id, task_id: Id) -> Result<()>;
+}
+
+pub trait Detach {
+    fn detach_tag(&mut self, tag_id: Id, task_id: Id) -> Result<()>;
+}
+
+fn fetch_existing_tag<R>(r: &mut R, name: &str) -> Result<Tag>
+where
+    R: Fetch,
+{
+    r.fetch_tag_by_name(name)?
+        .ok_or_else(|| RepositoryError::TagNotFound(name.to_string()).into())
+}
+
+pub fn add_tag<R>(r: &mut R, name: &str) -> Result<()>
+where
+    R: Add + Fetch,
+{
+    if r.fetch_tag_by_name(name)?.is_some() {
+        return Err(RepositoryError::TagAlreadyExists(name.to_string()).into());
+    }
+    r.add_tag(name)
+}
+
+pub fn fetch_all_tags<R>(r: &mut R) -> Result<Vec<Tag>>
+where
+    R: Fetch,
+{
+    r.fetch_all_tags()
+}
+
+pub fn fetch_all_task_tags<R>(r: &mut R) -> Result<Vec<TaskTag>>
+where
+    R: Fetch,
+{
+    r.fetch_all_task_tags()
+}
+
+pub fn remove_tag<R>(r: &mut R, name: &str) -> Result<()>
+where
+    R: Fetch + Remove,
+{
+    let tag = fetch_existing_tag(r, name)?;
+    r.remove_tag(tag.id)
+}
+
+pub fn tag_task<R>(r: &mut R, task_id: Id, name: &str) -> Result<()>
+where
+    R: Fetch + Attach + task::Fetch,
+{
+    let task = r
+        .fetch_task_by_id(task_id)?
+        .ok_or(RepositoryError::TaskNotFound(task_id))?;
+    let tag = fetch_existing_tag(r, name)?;
+    r.attach_tag(tag.id, task.id)
+}
+
+pub fn untag_task<R>(r: &mut R, task_id: Id, name: &str) -> Result<()>
+where
+    R: Fetch + Detach + task::Fetch,
+{
+    let task = r
+        .fetch_task_by_id(task_id)?
+        .ok_or(RepositoryError::TaskNotFound(task_id))?;
+    let tag = fetch_existing_tag(r, name)?;
+    r.detach_tag(tag.id, task.id)
+}
+
+/// Lists tasks in the lane which have the tag.
+pub fn list_tagged_tasks<R>(r: &mut R, lane_name: &str, name: &str) -> Result<Vec<task::Task>>
+where
+    R: Fetch + task::Fetch,
+{
+    let tag = fetch_existing_tag(r, name)?;
+    let tagged: Vec<Id> = r
+        .fetch_all_task_tags()?
+        .into_iter()
+        .filter(|t| t.tag_id == tag.id)
+        .map(|t| t.task_id)
+        .collect();
+    let tasks = r.fetch_all_tasks(lane_name)?;
+    Ok(tasks
+        .into_iter()
+        .filter(|t| tagged.contains(&t.id))
+        .collect())
+}
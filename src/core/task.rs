@@ -1,8 +1,12 @@
 use super::common::{Id, RepositoryError};
+use super::interruption;
 use super::lane;
+use super::pomodoro;
 use super::priority;
+use super::timer;
+use super::todo;
 use anyhow::Result;
-use chrono::serde::ts_milliseconds;
+use chrono::serde::{ts_milliseconds, ts_milliseconds_option};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -18,18 +22,41 @@ pub struct Task {
     pub created_at: DateTime<Utc>,
     #[serde(with = "ts_milliseconds")]
     pub updated_at: DateTime<Utc>,
+    /// Set when the task is removed. Archived task is hidden from listings until restored.
+    #[serde(with = "ts_milliseconds_option")]
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+/// Task with names of its lane and priority and everything recorded on it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Detail {
+    #[serde(flatten)]
+    pub task: Task,
+    pub lane_name: String,
+    pub priority_name: String,
+    /// Completed pomodoros
+    pub actual: usize,
+    /// Seconds worked on pomodoros not cancelled, excluding paused time
+    pub spent_sec: i64,
+    pub pomodoros: Vec<pomodoro::Pomodoro>,
+    pub interruptions: Vec<interruption::Interruption>,
+    /// Days the task was planned on todo
+    pub todo_dates: Vec<todo::TodoDate>,
 }
 
 pub trait Add {
-    fn add_task(&mut self, lane_id: Id, priority: Id, summary: &str, estimate: i64) -> Result<()>;
+    /// Returns id of added task. Its estimate is recorded as the first of history.
+    fn add_task(&mut self, lane_id: Id, priority: Id, summary: &str, estimate: i64) -> Result<Id>;
 }
 
 pub trait Fetch {
     fn fetch_task_by_id(&mut self, id: Id) -> Result<Option<Task>>;
     fn fetch_all_tasks(&mut self, lane_name: &str) -> Result<Vec<Task>>;
+    fn fetch_archived_tasks(&mut self) -> Result<Vec<Task>>;
 }
 
 pub trait Mod {
+    /// Changed estimate is appended to history together with the task.
     fn mod_task(
         &mut self,
         id: Id,
@@ -40,22 +67,71 @@ pub trait Mod {
     ) -> Result<()>;
 }
 
+pub trait Remove {
+    /// Soft delete. Pomodoros, todo and tags of the task are kept for restore.
+    fn archive_task(&mut self, id: Id) -> Result<()>;
+    fn restore_task(&mut self, id: Id) -> Result<()>;
+    /// Hard delete tasks archived before `before` with all rows depending on them.
+    /// Returns ids of purged tasks.
+    fn purge_tasks(&mut self, before: &DateTime<Utc>) -> Result<Vec<Id>>;
+}
+
 pub fn add_task<R>(
     r: &mut R,
     lane_name: &str,
     priority_name: &str,
     summary: &str,
     estimate: i64,
-) -> Result<()>
+) -> Result<Id>
 where
     R: Add + lane::Fetch + priority::Fetch,
 {
-    if let Some(lane) = r.fetch_lane_by_name(lane_name)? {
-        let prio = r.fetch_priority_by_name(priority_name)?;
-        r.add_task(lane.id, prio.id, summary, estimate)
-    } else {
-        Err(RepositoryError::NotFound.into())
-    }
+    let lane = lane::fetch_existing_lane(r, lane_name)?;
+    let prio = priority::fetch_existing_priority(r, priority_name)?;
+    r.add_task(lane.id, prio.id, summary, estimate)
+}
+
+pub fn get_task<R>(r: &mut R, id: Id) -> Result<Task>
+where
+    R: Fetch,
+{
+    Ok(r.fetch_task_by_id(id)?
+        .ok_or(RepositoryError::TaskNotFound(id))?)
+}
+
+/// Collects history of the task for `ly task show`.
+pub fn get_detail<R>(r: &mut R, id: Id) -> Result<Detail>
+where
+    R: Fetch + lane::Fetch + priority::Fetch + pomodoro::Fetch + interruption::Fetch + todo::Fetch,
+{
+    let task = get_task(r, id)?;
+    let lane_name = lane::fetch_all_lanes(r)?
+        .into_iter()
+        .find(|l| l.id == task.lane_id)
+        .map(|l| l.name)
+        .ok_or_else(|| RepositoryError::LaneNotFound(task.lane_id.to_string()))?;
+    let priority_name = priority::fetch_all_priority(r)?
+        .into_iter()
+        .find(|p| p.id == task.priority)
+        .map(|p| p.name)
+        .ok_or_else(|| RepositoryError::PriorityNotFound(task.priority.to_string()))?;
+    let pomodoros = pomodoro::list_by_task(r, id)?;
+    let actual = pomodoros.iter().filter(|p| p.is_completed()).count();
+    let spent_sec = pomodoros
+        .iter()
+        .filter(|p| p.outcome != pomodoro::Outcome::Cancelled)
+        .map(|p| p.spent_sec())
+        .sum();
+    Ok(Detail {
+        task,
+        lane_name,
+        priority_name,
+        actual,
+        spent_sec,
+        pomodoros,
+        interruptions: interruption::list_by_task(r, id)?,
+        todo_dates: r.fetch_todo_dates_by_task_id(id)?,
+    })
 }
 
 pub fn list_all_tasks<R>(r: &mut R, lane_name: &str) -> Result<Vec<Task>>
@@ -76,11 +152,56 @@ pub fn mod_task<R>(
 where
     R: Mod + lane::Fetch + priority::Fetch,
 {
-    let lane = lane_name
-        .and_then(|lane_name| r.fetch_lane_by_name(lane_name).ok().unwrap())
-        .map(|l| l.id);
-    let prio = priority_name
-        .and_then(|priority_name| r.fetch_priority_by_name(priority_name).ok())
-        .map(|p| p.id);
+    let lane = match lane_name {
+        Some(name) => Some(lane::fetch_existing_lane(r, name)?.id),
+        None => None,
+    };
+    let prio = match priority_name {
+        Some(name) => Some(priority::fetch_existing_priority(r, name)?.id),
+        None => None,
+    };
     r.mod_task(id, lane.as_ref(), prio.as_ref(), summary, estimate)
 }
+
+pub fn list_archived_tasks<R>(r: &mut R) -> Result<Vec<Task>>
+where
+    R: Fetch,
+{
+    r.fetch_archived_tasks()
+}
+
+/// Archives task. Refuses when the task is bound to current timer.
+pub fn remove_task<R>(r: &mut R, id: Id) -> Result<()>
+where
+    R: Fetch + Remove + timer::TimerTaskGet,
+{
+    let task = r
+        .fetch_task_by_id(id)?
+        .ok_or(RepositoryError::TaskNotFound(id))?;
+    if let Some(timer_task) = r.get_timer_task()? {
+        if timer_task.task_id == task.id {
+            return Err(RepositoryError::TaskOnTimer(task.id).into());
+        }
+    }
+    r.archive_task(task.id)
+}
+
+pub fn restore_task<R>(r: &mut R, id: Id) -> Result<()>
+where
+    R: Fetch + Remove,
+{
+    let task = r
+        .fetch_task_by_id(id)?
+        .ok_or(RepositoryError::TaskNotFound(id))?;
+    if task.archived_at.is_none() {
+        return Err(RepositoryError::TaskNotArchived(task.id).into());
+    }
+    r.restore_task(task.id)
+}
+
+pub fn purge_tasks<R>(r: &mut R, before: &DateTime<Utc>) -> Result<Vec<Id>>
+where
+    R: Remove,
+{
+    r.purge_tasks(before)
+}
@@ -1,9 +1,12 @@
+use super::breaks;
 use super::common::{Id, RepositoryError};
-use super::pomodoro;
+use super::event::{Event, Publish};
+use super::pomodoro::{self, Outcome};
 use super::task;
+use super::todo;
 use anyhow::{bail, Result};
-use chrono::serde::ts_milliseconds;
-use chrono::{DateTime, Utc};
+use chrono::serde::{ts_milliseconds, ts_milliseconds_option};
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::fmt::{Display, Formatter, Result as FmtResult};
@@ -27,7 +30,7 @@ pub struct TimerTypeFromIntError {
 
 impl Display for TimerTypeFromIntError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "Failed to convert TimerType from")
+        write!(f, "Failed to convert TimerType from {}", self.value)
     }
 }
 
@@ -37,9 +40,9 @@ impl std::error::Error for TimerTypeFromIntError {
     }
 }
 
-impl Into<u8> for TimerType {
-    fn into(self) -> u8 {
-        match self {
+impl From<TimerType> for u8 {
+    fn from(val: TimerType) -> Self {
+        match val {
             TimerType::ShortBreak => TIMER_TYPE_SHORT_BREAK,
             TimerType::LongBreak => TIMER_TYPE_LONG_BREAK,
             TimerType::Pomodoro => TIMER_TYPE_POMODORO,
@@ -59,7 +62,7 @@ impl TryFrom<u8> for TimerType {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Timer {
     pub id: Id,
     pub timer_type: TimerType,
@@ -67,6 +70,33 @@ pub struct Timer {
     #[serde(with = "ts_milliseconds")]
     pub started_at: DateTime<Utc>,
     pub duration_min: i64,
+    /// Set while the timer is paused
+    #[serde(with = "ts_milliseconds_option")]
+    pub paused_at: Option<DateTime<Utc>>,
+    /// Seconds spent in pauses already resumed
+    pub paused_sec: i64,
+}
+
+impl Timer {
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Time the timer has been running at `now`, excluding pauses.
+    pub fn elapsed(&self, now: DateTime<Utc>) -> Duration {
+        let until = self.paused_at.unwrap_or(now);
+        until - self.started_at - Duration::seconds(self.paused_sec)
+    }
+
+    /// Time spent in pauses until `now`, including the ongoing one.
+    pub fn paused(&self, now: DateTime<Utc>) -> Duration {
+        now - self.started_at - self.elapsed(now)
+    }
+
+    /// Time left until the timer expires. Negative when already expired.
+    pub fn remaining(&self, now: DateTime<Utc>) -> Duration {
+        Duration::minutes(self.duration_min) - self.elapsed(now)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -75,88 +105,181 @@ pub struct TimerTask {
     pub task_id: Id,
 }
 
-pub trait TimerTaskAdd {
-    fn add_timer_task(&mut self, task_id: &Id) -> Result<()>;
-}
-
-pub trait TimerTaskRemove {
-    fn remove_timer_task(&mut self) -> Result<()>;
-}
-
 pub trait TimerTaskGet {
     fn get_timer_task(&mut self) -> Result<Option<TimerTask>>;
 }
 
 pub trait Lifecycle {
-    /// Start timer
-    fn start(&mut self, timer_type: &TimerType, label: &str, duration_min: i64) -> Result<Timer>;
-    /// Clear timer
-    fn complete(&mut self) -> Result<()>;
+    /// Start timer working on `task_id`, all at once.
+    /// Fails with `TimerAlreadyRunning` when another timer exists.
+    fn start(
+        &mut self,
+        timer_type: &TimerType,
+        label: &str,
+        duration_min: i64,
+        task_id: Option<Id>,
+    ) -> Result<Timer>;
+    /// Clear `timer` and record it as `outcome` at `finished_at`, all at once.
+    /// Cancelled timer and voided break are not recorded.
+    /// Returns false without recording when `timer` is no longer the running one.
+    fn finish(
+        &mut self,
+        timer: &Timer,
+        outcome: &Outcome,
+        finished_at: DateTime<Utc>,
+    ) -> Result<bool>;
+    /// Stop counting down from `paused_at`
+    fn pause(&mut self, paused_at: DateTime<Utc>) -> Result<()>;
+    /// Restart counting down with total seconds spent in pauses
+    fn resume(&mut self, paused_sec: i64) -> Result<()>;
 }
 
 pub trait Get {
     fn get(&mut self) -> Result<Option<Timer>>;
 }
 
+/// Only one timer can exist at a time.
+fn ensure_no_timer<R>(r: &mut R) -> Result<()>
+where
+    R: Get,
+{
+    match r.get()? {
+        Some(_) => Err(RepositoryError::TimerAlreadyRunning.into()),
+        None => Ok(()),
+    }
+}
+
 pub fn pomodoro<R>(r: &mut R, task_id: Id, duration_min: i64) -> Result<Timer>
 where
-    R: Lifecycle + TimerTaskAdd + task::Fetch,
+    R: Lifecycle + task::Fetch + Publish,
 {
     let task = r
         .fetch_task_by_id(task_id)?
-        .ok_or(RepositoryError::NotFound)?;
-    let timer = r.start(&TimerType::Pomodoro, &task.summary, duration_min)?;
-    r.add_timer_task(&task.id)?;
+        .ok_or(RepositoryError::TaskNotFound(task_id))?;
+    if task.archived_at.is_some() {
+        return Err(RepositoryError::TaskArchived(task.id).into());
+    }
+    let timer = r.start(
+        &TimerType::Pomodoro,
+        &task.summary,
+        duration_min,
+        Some(task.id),
+    )?;
+    r.publish(&Event::TimerStarted(timer.clone()))?;
     Ok(timer)
 }
 
 pub fn take_break<R>(r: &mut R, timer_type: &TimerType, duration_min: i64) -> Result<Timer>
 where
-    R: Lifecycle,
+    R: Lifecycle + Publish,
 {
     let label: Result<&str, anyhow::Error> = match timer_type {
         TimerType::ShortBreak => Ok("short break"),
         TimerType::LongBreak => Ok("long break"),
         _ => bail!("illegal timer type {:?}", timer_type),
     };
-    let timer = r.start(timer_type, label?, duration_min)?;
+    let timer = r.start(timer_type, label?, duration_min, None)?;
+    r.publish(&Event::TimerStarted(timer.clone()))?;
     Ok(timer)
 }
 
-pub fn complete<R>(r: &mut R, timer: &Timer) -> Result<()>
+/// Finishes timer with `outcome` at `finished_at` and records the result.
+/// Cancelled timer and voided break are not recorded.
+/// Pomodoro is completed only when it has reached its deadline at `finished_at`.
+/// Fails when `timer` is no longer the running one, e.g. stopped from other process.
+pub fn stop<R>(
+    r: &mut R,
+    timer: &Timer,
+    outcome: &Outcome,
+    finished_at: DateTime<Utc>,
+) -> Result<()>
 where
-    R: Lifecycle + TimerTaskRemove + TimerTaskGet + task::Fetch + pomodoro::Complete,
+    R: Lifecycle + Publish,
 {
-    info!("compeling timer type: {:?}", timer.timer_type);
-    match timer.timer_type {
-        TimerType::ShortBreak => {
-            debug!("completing short break");
-        }
-        TimerType::LongBreak => {
-            debug!("completing long break");
-        }
-        TimerType::Pomodoro => match r.get_timer_task()? {
-            Some(timer_task) => {
-                info!(
-                    "current timer_task found: {} {}",
-                    timer_task.timer_id, timer_task.task_id
-                );
-                let task = r
-                    .fetch_task_by_id(timer_task.task_id)?
-                    .ok_or(RepositoryError::NotFound)?;
-                debug!("fetched task in pomodoro: {} {}", task.id, task.summary);
-                info!("fetched timer_task");
-                r.complete_pomodoro(task.id, timer.started_at)?;
-                debug!("completed pomodoro: {} {}", task.id, timer.started_at);
-            }
-            None => bail!("timer_task was not found"),
-        },
-    };
-    r.remove_timer_task()?;
-    debug!("removing timer task");
-    let result = r.complete();
+    if timer.timer_type == TimerType::Pomodoro
+        && *outcome == Outcome::Completed
+        && timer.remaining(finished_at) > Duration::zero()
+    {
+        return Err(RepositoryError::PomodoroNotFinished.into());
+    }
+    info!("stopping timer type: {:?} as {}", timer.timer_type, outcome);
+    if !r.finish(timer, outcome, finished_at)? {
+        return Err(RepositoryError::TimerAlreadyStopped.into());
+    }
     debug!("complete timer");
-    result
+    let event = match outcome {
+        Outcome::Completed => Event::TimerCompleted(timer.clone()),
+        Outcome::Voided => Event::TimerVoided(timer.clone()),
+        Outcome::Cancelled => Event::TimerCancelled(timer.clone()),
+    };
+    r.publish(&event)
+}
+
+/// Stops the running timer with `outcome` and returns it.
+pub fn stop_current<R>(r: &mut R, outcome: &Outcome) -> Result<Timer>
+where
+    R: Lifecycle + Get + Publish,
+{
+    let timer = r.get()?.ok_or(RepositoryError::TimerNotRunning)?;
+    stop(r, &timer, outcome, Utc::now())?;
+    Ok(timer)
+}
+
+/// Completes the running timer if it has expired at `now`, recording its deadline as finish time.
+/// Timer left by crashed or closed process is reconciled by this as well.
+pub fn expire<R>(r: &mut R, now: DateTime<Utc>) -> Result<Option<Timer>>
+where
+    R: Lifecycle + Get + Publish,
+{
+    let timer = match r.get()? {
+        Some(timer) if !timer.is_paused() => timer,
+        _ => return Ok(None),
+    };
+    let remaining = timer.remaining(now);
+    if remaining > Duration::zero() {
+        return Ok(None);
+    }
+    stop(r, &timer, &Outcome::Completed, now + remaining)?;
+    Ok(Some(timer))
+}
+
+/// Pauses the running timer at `now`.
+pub fn pause<R>(r: &mut R, now: DateTime<Utc>) -> Result<Timer>
+where
+    R: Lifecycle + Get + Publish,
+{
+    let timer = r.get()?.ok_or(RepositoryError::TimerNotRunning)?;
+    if timer.is_paused() {
+        return Err(RepositoryError::TimerPaused.into());
+    }
+    r.pause(now)?;
+    let paused = Timer {
+        paused_at: Some(now),
+        ..timer
+    };
+    r.publish(&Event::TimerPaused(paused.clone()))?;
+    Ok(paused)
+}
+
+/// Resumes the paused timer at `now`, adding the pause to the total paused time.
+pub fn resume<R>(r: &mut R, now: DateTime<Utc>) -> Result<Timer>
+where
+    R: Lifecycle + Get + Publish,
+{
+    let timer = r.get()?.ok_or(RepositoryError::TimerNotRunning)?;
+    let paused_at = match timer.paused_at {
+        Some(t) => t,
+        None => return Err(RepositoryError::TimerNotPaused.into()),
+    };
+    let paused_sec = timer.paused_sec + (now - paused_at).num_seconds().max(0);
+    r.resume(paused_sec)?;
+    let resumed = Timer {
+        paused_at: None,
+        paused_sec,
+        ..timer
+    };
+    r.publish(&Event::TimerResumed(resumed.clone()))?;
+    Ok(resumed)
 }
 
 pub fn get_current_timer<R>(r: &mut R) -> Result<Option<Timer>>
@@ -166,3 +289,89 @@ where
     let timer = r.get()?;
     Ok(timer)
 }
+
+/// What to start next in the pomodoro cycle.
+#[derive(Debug)]
+pub enum Next {
+    Pomodoro(todo::TodoTask),
+    Break(TimerType),
+}
+
+/// Decides which break is due after the pomodoros and breaks taken so far.
+/// Returns `None` when the last completed timer was a break (or nothing has been done yet).
+/// Long break is due when `interval` pomodoros were completed since the last long break.
+/// Voided and cancelled pomodoros are not part of the cycle.
+fn due_break(
+    pomodoros: &[pomodoro::Pomodoro],
+    breaks: &[breaks::Break],
+    interval: usize,
+) -> Option<TimerType> {
+    let pomodoros: Vec<&pomodoro::Pomodoro> =
+        pomodoros.iter().filter(|p| p.is_completed()).collect();
+    let last_pomodoro = pomodoros.iter().map(|p| p.started_at).max()?;
+    if breaks.iter().any(|b| b.started_at > last_pomodoro) {
+        return None;
+    }
+    let last_long_break = breaks
+        .iter()
+        .filter(|b| b.timer_type == TimerType::LongBreak)
+        .map(|b| b.started_at)
+        .max();
+    let in_set = pomodoros
+        .iter()
+        .filter(|p| last_long_break.is_none_or(|t| p.started_at > t))
+        .count();
+    if in_set >= interval {
+        Some(TimerType::LongBreak)
+    } else {
+        Some(TimerType::ShortBreak)
+    }
+}
+
+/// Suggests break type from pomodoros and breaks taken on the day of `now` in `timezone`.
+/// Short break is suggested when no break is due.
+pub fn suggest_break<R, Tz>(
+    r: &mut R,
+    now: DateTime<Utc>,
+    timezone: &Tz,
+    interval: usize,
+) -> Result<TimerType>
+where
+    R: pomodoro::Fetch + breaks::Fetch,
+    Tz: TimeZone,
+{
+    let start = todo::start_of_day_in_tz(now, timezone).with_timezone(&Utc);
+    let end = todo::end_of_day_in_tz(&start, timezone);
+    let pomodoros = r.fetch_pomodoros_between(&start, &end)?;
+    let breaks = r.fetch_breaks_between(&start, &end)?;
+    Ok(due_break(&pomodoros, &breaks, interval).unwrap_or(TimerType::ShortBreak))
+}
+
+/// Decides what comes next on the day of `now`: a break if one is due after the last pomodoro,
+/// otherwise a pomodoro on the first todo task which has not reached its estimate.
+pub fn next_in_cycle<R, Tz>(
+    r: &mut R,
+    now: DateTime<Utc>,
+    timezone: &Tz,
+    interval: usize,
+) -> Result<Next>
+where
+    R: Get + pomodoro::Fetch + breaks::Fetch + todo::Fetch,
+    Tz: TimeZone,
+{
+    ensure_no_timer(r)?;
+    let start = todo::start_of_day_in_tz(now, timezone).with_timezone(&Utc);
+    let end = todo::end_of_day_in_tz(&start, timezone);
+    let pomodoros = r.fetch_pomodoros_between(&start, &end)?;
+    let breaks = r.fetch_breaks_between(&start, &end)?;
+    if let Some(timer_type) = due_break(&pomodoros, &breaks, interval) {
+        return Ok(Next::Break(timer_type));
+    }
+    let task = todo::list_todo_tasks(r, &start, timezone)?
+        .into_iter()
+        .find(|t| t.actual < t.estimate);
+    match task {
+        Some(t) => Ok(Next::Pomodoro(t)),
+        None => bail!("no todo task left to work on today"),
+    }
+}
@@ -1,21 +1,54 @@
-use super::common::Id;
-use anyhow::Result;
+use super::common::{Id, RepositoryError};
+use super::event::{Event, Publish};
+use super::task;
+use anyhow::{bail, Result};
+use chrono::offset::LocalResult;
 use chrono::serde::ts_milliseconds;
-use chrono::{DateTime, Datelike, TimeZone, Utc};
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 //pub type TodoDate = NaiveDate;
 pub type TodoDate = DateTime<Utc>;
 
+/// Returns the first instant of `date` in specified timezone.
+/// When midnight is skipped by DST transition, the first valid local time of the day is used.
+pub fn start_of_date_in_tz<Tz: TimeZone>(date: NaiveDate, timezone: &Tz) -> DateTime<Tz> {
+    let mut local = date.and_hms(0, 0, 0);
+    loop {
+        match timezone.from_local_datetime(&local) {
+            LocalResult::Single(dt) => return dt,
+            LocalResult::Ambiguous(earliest, _) => return earliest,
+            LocalResult::None => local += Duration::minutes(1),
+        }
+    }
+}
+
 /// Returns timestamp of start of day in specified timezone from specified time point in (maybe) other timezone.
 pub fn start_of_day_in_tz<FromZone: TimeZone, ToZone: TimeZone>(
     ts: DateTime<FromZone>,
     timezone: &ToZone,
 ) -> DateTime<ToZone> {
-    let now_tz = ts.with_timezone(timezone);
-    timezone
-        .ymd(now_tz.year(), now_tz.month(), now_tz.day())
-        .and_hms(0, 0, 0)
+    let date = ts.with_timezone(timezone).naive_local().date();
+    start_of_date_in_tz(date, timezone)
+}
+
+/// Parses `YYYY-MM-DD` as the start of the date in `timezone`. Today when `input` is not given.
+pub fn parse_or_today<Tz: TimeZone>(timezone: &Tz, input: Option<&str>) -> Result<TodoDate> {
+    match input {
+        Some(input) => {
+            let date = NaiveDate::parse_from_str(input, "%Y-%m-%d")?;
+            Ok(start_of_date_in_tz(date, timezone).with_timezone(&Utc))
+        }
+        None => Ok(start_of_day_in_tz(Utc::now(), timezone).with_timezone(&Utc)),
+    }
+}
+
+/// Returns exclusive end of the day which `date` belongs to, i.e. start of the next day.
+/// This is not always 24 hours after `date` on DST transition.
+pub fn end_of_day_in_tz<Tz: TimeZone>(date: &TodoDate, timezone: &Tz) -> TodoDate {
+    let next = date.with_timezone(timezone).naive_local().date().succ();
+    start_of_date_in_tz(next, timezone).with_timezone(&Utc)
 }
 
 /// Task to be done.
@@ -39,6 +72,16 @@ pub struct TodoTask {
     pub summary: String,
     pub estimate: i64,
     pub actual: i64,
+    pub internal_interruptions: i64,
+    pub external_interruptions: i64,
+}
+
+/// Todo of a day with its tasks in planned order.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Plan {
+    pub date: TodoDate,
+    pub note: String,
+    pub tasks: Vec<TodoTask>,
 }
 
 pub trait Add {
@@ -47,58 +90,186 @@ pub trait Add {
 
 pub trait Fetch {
     fn fetch_by_date(&mut self, date: &TodoDate) -> Result<Option<Todo>>;
-    fn fetch_todo_tasks(&mut self, date: &TodoDate) -> Result<Vec<TodoTask>>;
+    /// Returns tasks planned on `date` with pomodoros counted until `end`.
+    fn fetch_todo_tasks(&mut self, date: &TodoDate, end: &TodoDate) -> Result<Vec<TodoTask>>;
+    /// Returns dates the task is planned on in ascending order.
+    fn fetch_todo_dates_by_task_id(&mut self, task_id: Id) -> Result<Vec<TodoDate>>;
 }
 
 pub trait Mod {
     fn add_todo_task(&mut self, date: &TodoDate, task_id: &Id, todo_order: usize) -> Result<()>;
     fn remove_todo_task(&mut self, date: &TodoDate, task_id: &Id) -> Result<()>;
+    fn mod_todo_note(&mut self, date: &TodoDate, note: &str) -> Result<()>;
+    /// Replaces every task planned on `date`, archived ones included, with `task_ids`
+    /// in this order and the note when given, all at once. Creates the todo when missing.
+    fn replace_todo_tasks(
+        &mut self,
+        date: &TodoDate,
+        task_ids: &[Id],
+        note: Option<&str>,
+    ) -> Result<()>;
 }
 
-pub fn list_todo_tasks<R>(r: &mut R, date: &TodoDate) -> Result<Vec<TodoTask>>
+pub fn list_todo_tasks<R, Tz>(r: &mut R, date: &TodoDate, timezone: &Tz) -> Result<Vec<TodoTask>>
 where
     R: Fetch,
+    Tz: TimeZone,
 {
     debug!("list tasks on date {:?}", date);
-    r.fetch_todo_tasks(date)
+    r.fetch_todo_tasks(date, &end_of_day_in_tz(date, timezone))
 }
 
-pub fn mod_todo<R>(r: &mut R, date: &TodoDate, add_tasks: &[Id], remove_tasks: &[Id]) -> Result<()>
+/// Returns todo on `date`, creating empty one if it does not exist yet.
+fn ensure_todo<R>(r: &mut R, date: &TodoDate) -> Result<Todo>
 where
-    R: Fetch + Mod + Add,
+    R: Fetch + Add,
 {
-    let plan = match r.fetch_by_date(date)? {
-        Some(plan) => plan,
+    match r.fetch_by_date(date)? {
+        Some(plan) => Ok(plan),
         None => {
             // otherwise create
             r.add_todo(date, "")?;
             let msg = format!("could not find inserted plan on date: {:?}", date);
-            r.fetch_by_date(date).map(|o| o.expect(&msg))?
+            r.fetch_by_date(date).map(|o| o.expect(&msg))
         }
-    };
-    for (i, t) in add_tasks.iter().enumerate() {
-        r.add_todo_task(&plan.date, t, i)?;
     }
-    for i in remove_tasks {
-        r.remove_todo_task(&plan.date, i)?;
+}
+
+pub fn get_plan<R, Tz>(r: &mut R, date: &TodoDate, timezone: &Tz) -> Result<Plan>
+where
+    R: Fetch,
+    Tz: TimeZone,
+{
+    let note = r.fetch_by_date(date)?.map(|t| t.note).unwrap_or_default();
+    let tasks = list_todo_tasks(r, date, timezone)?;
+    Ok(Plan {
+        date: *date,
+        note,
+        tasks,
+    })
+}
+
+pub fn set_note<R>(r: &mut R, date: &TodoDate, note: &str) -> Result<()>
+where
+    R: Fetch + Mod + Add + Publish,
+{
+    let plan = ensure_todo(r, date)?;
+    r.mod_todo_note(&plan.date, note)?;
+    r.publish(&Event::TodoChanged { date: plan.date })
+}
+
+/// Fails unless every task exists, is not archived and appears only once.
+fn validate_task_ids<R>(r: &mut R, task_ids: &[Id]) -> Result<()>
+where
+    R: task::Fetch,
+{
+    let mut seen = HashSet::new();
+    for id in task_ids {
+        if !seen.insert(id) {
+            bail!("task {} is listed more than once", id);
+        }
+        ensure_plannable(r, *id)?;
     }
     Ok(())
 }
 
-pub fn clear_todo<R>(r: &mut R, date: &TodoDate) -> Result<()>
+/// Fails unless the task exists and is not archived.
+pub fn ensure_plannable<R>(r: &mut R, task_id: Id) -> Result<()>
 where
-    R: Fetch + Mod + Add,
+    R: task::Fetch,
 {
-    let tasks = r.fetch_todo_tasks(date)?;
-    for t in tasks {
-        r.remove_todo_task(date, &t.task_id)?;
+    match r.fetch_task_by_id(task_id)? {
+        Some(t) if t.archived_at.is_some() => Err(RepositoryError::TaskArchived(task_id).into()),
+        Some(_) => Ok(()),
+        None => Err(RepositoryError::TaskNotFound(task_id).into()),
     }
-    Ok(())
+}
+
+/// Replaces tasks planned on `date` with `task_ids` in this order, and its note when given.
+pub fn replace_todo<R>(
+    r: &mut R,
+    date: &TodoDate,
+    task_ids: &[Id],
+    note: Option<&str>,
+) -> Result<()>
+where
+    R: Mod + task::Fetch + Publish,
+{
+    validate_task_ids(r, task_ids)?;
+    r.replace_todo_tasks(date, task_ids, note)?;
+    r.publish(&Event::TodoChanged { date: *date })
+}
+
+/// Changes order of tasks planned on `date`. `task_ids` must consist of the planned tasks.
+pub fn reorder_todo<R, Tz>(r: &mut R, date: &TodoDate, timezone: &Tz, task_ids: &[Id]) -> Result<()>
+where
+    R: Fetch + Mod + Add + task::Fetch + Publish,
+    Tz: TimeZone,
+{
+    let planned: HashSet<Id> = list_todo_tasks(r, date, timezone)?
+        .iter()
+        .map(|t| t.task_id)
+        .collect();
+    let given: HashSet<Id> = task_ids.iter().copied().collect();
+    if given.len() != task_ids.len() || planned != given {
+        bail!("task ids must be the tasks planned on the date");
+    }
+    replace_todo(r, date, task_ids, None)
+}
+
+/// Appends task to the end of todo on `date`.
+pub fn append_todo_task<R, Tz>(r: &mut R, date: &TodoDate, timezone: &Tz, task_id: Id) -> Result<()>
+where
+    R: Fetch + Mod + Add + task::Fetch + Publish,
+    Tz: TimeZone,
+{
+    ensure_plannable(r, task_id)?;
+    let tasks = list_todo_tasks(r, date, timezone)?;
+    if tasks.iter().any(|t| t.task_id == task_id) {
+        bail!("task {} is already planned", task_id);
+    }
+    let plan = ensure_todo(r, date)?;
+    r.add_todo_task(&plan.date, &task_id, tasks.len())?;
+    r.publish(&Event::TodoChanged { date: plan.date })
+}
+
+pub fn remove_todo_task<R, Tz>(r: &mut R, date: &TodoDate, timezone: &Tz, task_id: Id) -> Result<()>
+where
+    R: Fetch + Mod + Publish,
+    Tz: TimeZone,
+{
+    let tasks = list_todo_tasks(r, date, timezone)?;
+    if tasks.iter().all(|t| t.task_id != task_id) {
+        return Err(RepositoryError::TaskNotPlanned(task_id).into());
+    }
+    r.remove_todo_task(date, &task_id)?;
+    r.publish(&Event::TodoChanged { date: *date })
 }
 
 #[cfg(test)]
 mod tests {
-    use chrono::{FixedOffset, TimeZone, Utc};
+    use anyhow::Result;
+    use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    #[test]
+    fn test_parse_or_today() -> Result<()> {
+        let input = "2021-01-01";
+        let jst = FixedOffset::east(9 * 3600);
+        let parsed: DateTime<Utc> = super::parse_or_today(&jst, Some(input))?;
+        let expected = Utc.datetime_from_str("2020-12-31 15:00:00", "%Y-%m-%d %H:%M:%S")?;
+        assert_eq!(parsed, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_or_today_in_dst() -> Result<()> {
+        let input = "2021-07-01";
+        let parsed: DateTime<Utc> = super::parse_or_today(&Tz::Europe__Berlin, Some(input))?;
+        let expected = Utc.datetime_from_str("2021-06-30 22:00:00", "%Y-%m-%d %H:%M:%S")?;
+        assert_eq!(parsed, expected);
+        Ok(())
+    }
 
     #[test]
     fn test_start_of_day_in_tz() {
@@ -110,4 +281,29 @@ mod tests {
             "2021-03-07 00:00:00"
         );
     }
+
+    #[test]
+    fn test_start_of_day_in_dst_zone() {
+        // 2021-03-28 is the day DST starts in Europe/Berlin
+        let timestamp_utc = Utc.ymd(2021, 3, 28).and_hms(12, 0, 0);
+        let start_of_day = super::start_of_day_in_tz(timestamp_utc, &Tz::Europe__Berlin);
+        assert_eq!(
+            start_of_day.with_timezone(&Utc),
+            Utc.ymd(2021, 3, 27).and_hms(23, 0, 0)
+        );
+        let end_of_day =
+            super::end_of_day_in_tz(&start_of_day.with_timezone(&Utc), &Tz::Europe__Berlin);
+        assert_eq!(end_of_day, Utc.ymd(2021, 3, 28).and_hms(22, 0, 0));
+    }
+
+    #[test]
+    fn test_start_of_day_skipped_midnight() {
+        // Midnight did not exist in America/Sao_Paulo on 2018-11-04
+        let timestamp_utc = Utc.ymd(2018, 11, 4).and_hms(12, 0, 0);
+        let start_of_day = super::start_of_day_in_tz(timestamp_utc, &Tz::America__Sao_Paulo);
+        assert_eq!(
+            start_of_day.format("%Y-%m-%d %H:%M:%S").to_string(),
+            "2018-11-04 01:00:00"
+        );
+    }
 }
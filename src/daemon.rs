@@ -0,0 +1,29 @@
+use crate::core::{timer, Repository};
+use crate::sql::Session;
+use anyhow::Result;
+use chrono::Utc;
+use tokio::sync::Mutex;
+
+/// Interval to check expiry of the running timer.
+const CHECK_INTERVAL_SEC: u64 = 1;
+
+/// Completes the running timer when it expires.
+pub fn expire_timer<R: Repository>(r: &mut R) -> Result<()> {
+    if let Some(t) = timer::expire(r, Utc::now())? {
+        info!("completed expired timer: {} {}", t.label, t.started_at);
+    }
+    Ok(())
+}
+
+/// Keeps completing timers at their deadline. Never returns.
+/// Timer expired while no daemon was running is completed at the first check.
+/// `session` is locked only while checking so that it can be shared with the server.
+pub async fn watch_timer(session: &Mutex<Session>) -> Result<()> {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(CHECK_INTERVAL_SEC));
+    loop {
+        interval.tick().await;
+        if let Err(e) = expire_timer(&mut *session.lock().await) {
+            warn!("failed to check timer expiry: {:?}", e);
+        }
+    }
+}
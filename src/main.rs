@@ -1,23 +1,46 @@
 #[macro_use]
 extern crate log;
-use crate::cli::TaskContext;
+use crate::cli::{Output, TaskContext, TodoSummary};
+use crate::core::pomodoro::Outcome;
 use crate::core::timer;
-use crate::core::Id;
-use anyhow::Result;
-use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use crate::core::{Id, RepositoryError};
+use crate::rpc::{Execute, Request, Settings};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
 use clap::{ArgEnum, Parser, Subcommand};
-use std::convert::TryFrom;
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 mod cli;
 mod config;
 mod core;
+mod daemon;
 mod public;
+mod rpc;
 mod sql;
 mod web;
 
+/// Lane of finished tasks created by `ly init`.
+const DONE_LANE: &str = "done";
+
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 struct Ly {
+    /// Path to config file
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+    /// Path to database file
+    #[clap(long, global = true)]
+    database: Option<PathBuf>,
+    /// IANA timezone name like Asia/Tokyo
+    #[clap(long, global = true)]
+    timezone: Option<String>,
+    /// URL of ly server to send commands to, like http://localhost:8080
+    #[clap(long, global = true)]
+    remote: Option<String>,
+    /// Format of listings
+    #[clap(short, long, global = true, arg_enum)]
+    output: Option<Output>,
     #[clap(subcommand)]
     command: Command,
 }
@@ -29,24 +52,66 @@ enum Command {
     /// Start ly server
     Server {
         /// Address to bind
-        #[clap(short, long, default_value_t = String::from("0.0.0.0"))]
-        address: String,
+        #[clap(short, long)]
+        address: Option<String>,
         /// Port number to listen
         #[clap(short, long)]
-        port: u16,
+        port: Option<u16>,
     },
-    /// Start pomodoro
+    /// Complete timers at their deadline without serving web UI
+    Daemon {},
+    /// Start pomodoro. Timer is completed by server or daemon
     Start {
         /// Task ID
         #[clap(short, long)]
         id: i64,
         /// Pomodoro duration
         #[clap(short, long)]
-        duration: i64,
+        duration: Option<i64>,
+        /// Wait in the terminal until the timer expires
+        #[clap(short, long)]
+        wait: bool,
     },
+    /// Take break. Break type is chosen from the pomodoro cycle unless given
     Break {
         #[clap(arg_enum)]
-        break_type: BreakType,
+        break_type: Option<BreakType>,
+        /// Break duration
+        #[clap(short, long)]
+        duration: Option<i64>,
+        /// Wait in the terminal until the timer expires
+        #[clap(short, long)]
+        wait: bool,
+    },
+    /// Stop running timer. Pomodoro is completed only after its deadline, otherwise void or cancel it
+    Stop {
+        /// Abandon interrupted pomodoro. It is recorded but not counted as actual
+        #[clap(long, conflicts_with = "cancel")]
+        void: bool,
+        /// Discard timer started by mistake
+        #[clap(long)]
+        cancel: bool,
+    },
+    /// Pause running timer
+    Pause {},
+    /// Resume paused timer
+    Resume {},
+    /// Start whatever comes next in the cycle: pomodoro on next todo task or break
+    Next {
+        /// Wait in the terminal until the timer expires
+        #[clap(short, long)]
+        wait: bool,
+    },
+    /// Record interruption on current pomodoro
+    #[clap(args_conflicts_with_subcommands = true)]
+    Interrupt {
+        /// Interrupted by other people rather than by yourself
+        #[clap(short, long)]
+        external: bool,
+        #[clap(short, long)]
+        note: Option<String>,
+        #[clap(subcommand)]
+        interrupt_command: Option<InterruptCommand>,
     },
     Task {
         #[clap(subcommand)]
@@ -56,6 +121,115 @@ enum Command {
         #[clap(subcommand)]
         todo_command: TodoCommand,
     },
+    /// Manage tags to classify tasks
+    Tag {
+        #[clap(subcommand)]
+        tag_command: TagCommand,
+    },
+    /// Manage lanes which tasks move through
+    Lane {
+        #[clap(subcommand)]
+        lane_command: LaneCommand,
+    },
+    /// Manage priorities which order tasks
+    Priority {
+        #[clap(subcommand)]
+        priority_command: PriorityCommand,
+    },
+    /// Inspect configuration
+    Config {
+        #[clap(subcommand)]
+        config_command: ConfigCommand,
+    },
+    /// Manage database schema
+    Db {
+        #[clap(subcommand)]
+        db_command: DbCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagCommand {
+    Ls {},
+    Add {
+        #[clap(short, long)]
+        name: String,
+    },
+    /// Delete tag and detach it from all tasks
+    Rm {
+        #[clap(short, long)]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum LaneCommand {
+    /// List lanes in process order
+    Ls {},
+    /// Add lane at the end of process
+    Add {
+        #[clap(short, long)]
+        name: String,
+    },
+    Rename {
+        #[clap(short, long)]
+        name: String,
+        /// New name of the lane
+        #[clap(long)]
+        to: String,
+    },
+    /// Delete lane. Lane with tasks requires --move-to
+    Rm {
+        #[clap(short, long)]
+        name: String,
+        /// Lane to move the remaining tasks to
+        #[clap(long)]
+        move_to: Option<String>,
+    },
+    /// Set process order by listing every lane
+    Reorder { names: Vec<String> },
+}
+
+#[derive(Subcommand)]
+enum PriorityCommand {
+    /// List priorities from the highest
+    Ls {},
+    /// Add priority above the existing ones
+    Add {
+        #[clap(short, long)]
+        name: String,
+        /// Short name accepted in place of the name
+        #[clap(short, long)]
+        alias: Option<String>,
+    },
+    /// Change name and/or alias of priority
+    Rename {
+        /// Name or alias of the priority
+        #[clap(short, long)]
+        name: String,
+        /// New name of the priority
+        #[clap(long)]
+        to: Option<String>,
+        /// New alias of the priority
+        #[clap(short, long)]
+        alias: Option<String>,
+    },
+    /// Set ranks by listing every priority from the highest
+    Reorder { names: Vec<String> },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print effective configuration and where each value came from
+    Show {},
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Apply pending schema migrations
+    Migrate {},
+    /// Show schema version and pending migrations
+    Status {},
 }
 
 #[derive(Subcommand)]
@@ -63,12 +237,19 @@ enum TaskCommand {
     Ls {
         #[clap(short, long, default_value_t = String::from("backlog"))]
         lane: String,
+        /// List removed tasks instead
+        #[clap(long)]
+        archived: bool,
+        /// List only tasks with the tag
+        #[clap(short, long)]
+        tag: Option<String>,
     },
     Add {
         #[clap(short, long)]
         summary: String,
         #[clap(short, long, default_value_t = String::from("backlog"))]
         lane: String,
+        /// Name or alias of priority
         #[clap(short, long, default_value_t = String::from("n"))]
         priority: String,
         #[clap(short, long, default_value_t = 1)]
@@ -81,15 +262,66 @@ enum TaskCommand {
         summary: Option<String>,
         #[clap(short, long)]
         lane: Option<String>,
+        /// Name or alias of priority
         #[clap(short, long)]
         priority: Option<String>,
         #[clap(short, long)]
         estimate: Option<i64>,
     },
+    /// Show task with its pomodoros, interruptions and todo dates
+    Show { id: i64 },
+    /// Remove task. Removed task can be restored until purged
     Rm {
         #[clap(short, long)]
         id: i64,
     },
+    /// Restore removed task
+    Restore {
+        #[clap(short, long)]
+        id: i64,
+    },
+    /// Show history of estimate of the task
+    Estimates {
+        #[clap(short, long)]
+        id: i64,
+    },
+    /// Compare first and final estimates with actual pomodoros
+    Accuracy {
+        #[clap(short, long, default_value_t = String::from(DONE_LANE))]
+        lane: String,
+    },
+    /// Attach tag to task
+    Tag {
+        #[clap(short, long)]
+        id: i64,
+        #[clap(short, long)]
+        tag: String,
+    },
+    /// Detach tag from task
+    Untag {
+        #[clap(short, long)]
+        id: i64,
+        #[clap(short, long)]
+        tag: String,
+    },
+    /// Delete removed tasks and their pomodoros permanently
+    Purge {
+        /// Purge tasks removed more than this days ago
+        #[clap(long)]
+        older_than: i64,
+    },
+}
+
+#[derive(Subcommand)]
+enum InterruptCommand {
+    /// List interruptions of the task or on the date
+    Ls {
+        /// Task ID
+        #[clap(short, long)]
+        id: Option<i64>,
+        #[clap(short, long)]
+        date: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -102,6 +334,17 @@ enum TodoCommand {
         #[clap(short, long)]
         date: Option<String>,
     },
+    /// Edit todo in $EDITOR with backlog tasks listed as comments
+    Edit {
+        #[clap(short, long)]
+        date: Option<String>,
+    },
+    /// Print note of the day, or replace it when given
+    Note {
+        #[clap(short, long)]
+        date: Option<String>,
+        note: Option<String>,
+    },
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
@@ -110,46 +353,32 @@ pub enum BreakType {
     Long,
 }
 
-struct CleanupCurrent {
-    session: sql::Session,
-    timer: timer::Timer,
-}
-
-impl Drop for CleanupCurrent {
-    fn drop(&mut self) {
-        let _ = crate::core::timer::complete(&mut self.session, &self.timer);
-        debug!("completed timer started_at: {}", &self.timer.started_at);
-    }
-}
-
-fn parse_line_as_task_ids<F>(input: F) -> Result<Vec<Id>>
-where
-    F: std::io::BufRead,
-{
-    let mut ids: Vec<Id> = Vec::new();
-    for line in input.lines() {
-        let l = line?;
-        if l.starts_with('#') {
-            debug!("skip line starts with hash: {:?}", l);
+/// Validates task ids read from lines and reports every invalid one with its line number.
+/// The first [`RepositoryError`] is kept as the source so that the exit status tells its kind.
+fn check_task_lines<E: Execute>(e: &mut E, lines: &[(usize, Id)]) -> Result<Vec<Id>> {
+    let mut first_seen: HashMap<Id, usize> = HashMap::new();
+    let mut errors = Vec::new();
+    let mut source: Option<anyhow::Error> = None;
+    for (line, id) in lines {
+        if let Some(first) = first_seen.get(id) {
+            errors.push(format!(
+                "line {}: task {} is already listed on line {}",
+                line, id, first
+            ));
             continue;
-        } else if let Some(id_str) = l.split('\t').next() {
-            debug!("read as task_id {:?}", id_str);
-            let id_int = id_str.parse::<i64>()?;
-            ids.push(id_int);
         }
-    }
-    Ok(ids)
-}
-
-fn parse_or_today(timezone: &FixedOffset, input: Option<&str>) -> Result<DateTime<Utc>> {
-    match input {
-        Some(input) => {
-            let mut dt = String::from(input);
-            dt.push_str("T00:00:00");
-            let parsed = timezone.datetime_from_str(&dt, "%Y-%m-%dT%H:%M:%S")?;
-            Ok(parsed.with_timezone(&Utc))
+        first_seen.insert(*id, *line);
+        if let Err(err) = e.execute::<()>(Request::EnsurePlannable { task_id: *id }) {
+            errors.push(format!("line {}: {:#}", line, err));
+            if source.is_none() && err.is::<RepositoryError>() {
+                source = Some(err);
+            }
         }
-        None => Ok(core::todo::start_of_day_in_tz(Utc::now(), timezone).with_timezone(&Utc)),
+    }
+    match (errors.is_empty(), source) {
+        (true, _) => Ok(lines.iter().map(|(_, id)| *id).collect()),
+        (false, Some(source)) => Err(source.context(errors.join("\n"))),
+        (false, None) => bail!(errors.join("\n")),
     }
 }
 
@@ -159,66 +388,304 @@ fn format_date(conf: &config::Config, date: DateTime<Utc>) -> String {
         .to_string()
 }
 
-fn start_pomodoro(conf: &config::Config, task_id: Id, duration_min: i64) -> Result<()> {
-    let timer: Result<timer::Timer> = {
-        let mut session = sql::Session::connect(conf)?;
-        crate::core::timer::pomodoro(&mut session, task_id, duration_min)
-    };
-    let session = sql::Session::connect(conf)?;
-    let mut _cleanup = CleanupCurrent {
-        session,
-        timer: timer?,
+fn print_timer(timer: &timer::Timer) {
+    let remaining = timer.remaining(Utc::now()).num_seconds().max(0);
+    let state = if timer.is_paused() {
+        "paused"
+    } else {
+        "running"
     };
-    let duration_sec: u64 = u64::try_from(duration_min * 60).expect("failed to cast i64 to u64");
-    debug!("starting to sleep: {}", duration_sec);
-    std::thread::sleep(std::time::Duration::from_secs(duration_sec));
-    debug!("sleep finished");
+    println!(
+        "{}\t{:02}:{:02}\t{}",
+        state,
+        remaining / 60,
+        remaining % 60,
+        timer.label
+    );
+}
+
+/// Resolves when the process is asked to quit by Ctrl-C, SIGTERM or SIGHUP.
+async fn quit_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut hangup = signal(SignalKind::hangup())?;
+        tokio::select! {
+            r = tokio::signal::ctrl_c() => r?,
+            _ = terminate.recv() => {}
+            _ = hangup.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
     Ok(())
 }
 
-fn start_break(conf: &config::Config, break_type: BreakType) -> Result<()> {
-    let (timer_type, duration_min) = match break_type {
-        BreakType::Short => (core::timer::TimerType::ShortBreak, conf.short_break),
-        BreakType::Long => (core::timer::TimerType::LongBreak, conf.long_break),
-    };
-    let timer: Result<timer::Timer> = {
-        let mut session = sql::Session::connect(conf)?;
-        crate::core::timer::take_break(&mut session, &timer_type, duration_min)
-    };
-    let session = sql::Session::connect(conf)?;
-    let mut _cleanup = CleanupCurrent {
-        session,
-        timer: timer?,
-    };
-    let duration_sec: u64 = u64::try_from(duration_min * 60).expect("failed to cast i64 to u64");
-    std::thread::sleep(std::time::Duration::from_secs(duration_sec));
+/// Waits for the timer to expire and records it as completed.
+/// Quitting by signal voids the timer. Returns without recording when the timer
+/// was stopped by other process like `ly stop`.
+async fn wait_timer<E: Execute>(e: &mut E, timer: timer::Timer) -> Result<()> {
+    let signal = quit_signal();
+    tokio::pin!(signal);
+    let second = std::time::Duration::from_secs(1);
+    loop {
+        let current = match e.execute::<Option<timer::Timer>>(Request::GetTimer)? {
+            Some(current) if current.started_at == timer.started_at => current,
+            _ => {
+                debug!("timer was stopped by other process");
+                return Ok(());
+            }
+        };
+        // paused timer is checked every second until resumed
+        let tick = if current.is_paused() {
+            second
+        } else {
+            let remaining = current.remaining(Utc::now());
+            if remaining <= chrono::Duration::zero() {
+                break;
+            }
+            remaining.to_std()?.min(second)
+        };
+        tokio::select! {
+            r = &mut signal => {
+                r?;
+                let voided: timer::Timer = e.execute(Request::Stop {
+                    outcome: Outcome::Voided,
+                    started_at: Some(timer.started_at),
+                })?;
+                println!("{}\t{}", Outcome::Voided, voided.label);
+                return Ok(());
+            }
+            _ = tokio::time::sleep(tick) => {}
+        }
+    }
+    match e.execute::<Option<timer::Timer>>(Request::Expire)? {
+        Some(expired) if expired.started_at == timer.started_at => {
+            println!("{}\t{}", Outcome::Completed, expired.label)
+        }
+        _ => debug!("timer was stopped by other process"),
+    }
+    Ok(())
+}
+
+async fn start_timer<E: Execute>(e: &mut E, request: Request, wait: bool) -> Result<()> {
+    let timer: timer::Timer = e.execute(request)?;
+    print_timer(&timer);
+    if wait {
+        wait_timer(e, timer).await?;
+    }
     Ok(())
 }
 
+/// Exit status telling failure kinds apart for scripts. 2 is used by clap for usage error.
+fn exit_code(e: &anyhow::Error) -> i32 {
+    match e.downcast_ref::<RepositoryError>() {
+        Some(RepositoryError::TaskNotFound(_)) => 10,
+        Some(RepositoryError::LaneNotFound(_)) => 11,
+        Some(RepositoryError::PriorityNotFound(_)) => 12,
+        Some(RepositoryError::TagNotFound(_)) => 13,
+        Some(RepositoryError::TaskNotPlanned(_)) => 14,
+        Some(RepositoryError::TimerNotRunning) => 15,
+        Some(RepositoryError::TimerTaskNotFound) => 16,
+        Some(RepositoryError::TimerAlreadyRunning) => 20,
+        Some(RepositoryError::LaneAlreadyExists(_))
+        | Some(RepositoryError::PriorityAlreadyExists(_))
+        | Some(RepositoryError::TagAlreadyExists(_)) => 21,
+        Some(RepositoryError::TaskArchived(_)) => 22,
+        Some(RepositoryError::PomodoroNotFinished) => 23,
+        Some(RepositoryError::TaskOnTimer(_)) => 24,
+        Some(RepositoryError::TaskNotArchived(_)) => 25,
+        Some(RepositoryError::TimerAlreadyStopped) => 26,
+        Some(RepositoryError::TimerPaused) => 27,
+        Some(RepositoryError::TimerNotPaused) => 28,
+        Some(RepositoryError::LaneListedTwice(_))
+        | Some(RepositoryError::PriorityListedTwice(_)) => 29,
+        None => 1,
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     env_logger::init();
+    if let Err(e) = ly_main().await {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(exit_code(&e));
+    }
+}
+
+async fn ly_main() -> Result<()> {
     let ly = Ly::parse();
 
-    let conf = config::Config::from_env()?;
+    let mut overrides = config::Layer {
+        database: ly.database,
+        timezone: ly.timezone,
+        remote: ly.remote,
+        ..config::Layer::default()
+    };
+    if let Command::Server { address, port } = &ly.command {
+        overrides.address = address.clone();
+        overrides.port = *port;
+    }
+    let conf = config::Config::load(ly.config.as_deref(), overrides)?;
+    let output = ly.output.unwrap_or_else(Output::detect);
     match ly.command {
         Command::Init {} => {
             let mut session = sql::Session::connect(&conf)?;
             session.initialize()?;
             Ok(())
         }
-        Command::Server { address, port } => web::start_server(conf, address, port).await,
-        Command::Start { id, duration } => start_pomodoro(&conf, id, duration),
-        Command::Break { break_type } => start_break(&conf, break_type),
-        Command::Task { task_command } => match task_command {
-            TaskCommand::Ls { lane } => {
+        Command::Server { .. } => web::start_server(conf).await,
+        Command::Daemon {} => {
+            let session = sql::Session::connect(&conf)?;
+            session.check_schema()?;
+            let session = tokio::sync::Mutex::new(session);
+            tokio::select! {
+                r = daemon::watch_timer(&session) => r,
+                r = quit_signal() => r,
+            }
+        }
+        Command::Config { config_command } => match config_command {
+            ConfigCommand::Show {} => {
+                for (key, value, origin) in conf.entries() {
+                    println!("{}\t{}\t{}", key, value, origin);
+                }
+                Ok(())
+            }
+        },
+        Command::Db { db_command } => match db_command {
+            DbCommand::Migrate {} => {
                 let mut session = sql::Session::connect(&conf)?;
-                let tasks = core::task::list_all_tasks(&mut session, &lane)?;
-                let lanes = core::lane::fetch_all_lanes(&mut session)?;
-                let priorities = core::priority::fetch_all_priority(&mut session)?;
-                let context = TaskContext::new(&lanes, &priorities);
-                for t in tasks {
-                    println!("{}", context.format(t));
+                for version in session.migrate()? {
+                    println!("applied\t{}", version);
+                }
+                Ok(())
+            }
+            DbCommand::Status {} => {
+                let session = sql::Session::connect(&conf)?;
+                println!(
+                    "#version:{}\tlatest:{}",
+                    session.schema_version()?,
+                    sql::ddl::latest_version()
+                );
+                for m in session.pending_migrations()? {
+                    println!("pending\t{}\t{}", m.version, m.description);
+                }
+                Ok(())
+            }
+        },
+        command => match conf.remote.clone() {
+            Some(url) => {
+                let mut client = rpc::client::Client::new(&url, Settings::from(&conf));
+                run(&mut client, &conf, output, command).await
+            }
+            None => {
+                let session = sql::Session::connect(&conf)?;
+                session.check_schema()?;
+                let mut local = rpc::Local::new(session, Settings::from(&conf));
+                run(&mut local, &conf, output, command).await
+            }
+        },
+    }
+}
+
+/// Runs command which works on both local database and remote server.
+async fn run<E: Execute>(
+    e: &mut E,
+    conf: &config::Config,
+    output: Output,
+    command: Command,
+) -> Result<()> {
+    match command {
+        Command::Start { id, duration, wait } => {
+            let request = Request::Start {
+                task_id: id,
+                duration_min: duration,
+            };
+            start_timer(e, request, wait).await
+        }
+        Command::Break {
+            break_type,
+            duration,
+            wait,
+        } => {
+            let timer_type = break_type.map(|t| match t {
+                BreakType::Short => timer::TimerType::ShortBreak,
+                BreakType::Long => timer::TimerType::LongBreak,
+            });
+            let request = Request::Break {
+                timer_type,
+                duration_min: duration,
+            };
+            start_timer(e, request, wait).await
+        }
+        Command::Stop { void, cancel } => {
+            let outcome = if void {
+                Outcome::Voided
+            } else if cancel {
+                Outcome::Cancelled
+            } else {
+                Outcome::Completed
+            };
+            let timer: timer::Timer = e.execute(Request::Stop {
+                outcome,
+                started_at: None,
+            })?;
+            println!("{}\t{}", outcome, timer.label);
+            Ok(())
+        }
+        Command::Pause {} => {
+            print_timer(&e.execute(Request::Pause)?);
+            Ok(())
+        }
+        Command::Resume {} => {
+            print_timer(&e.execute(Request::Resume)?);
+            Ok(())
+        }
+        Command::Next { wait } => start_timer(e, Request::Next, wait).await,
+        Command::Interrupt {
+            external,
+            note,
+            interrupt_command,
+        } => match interrupt_command {
+            None => {
+                let task_id: Id = e.execute(Request::Interrupt { external, note })?;
+                debug!("recorded interruption on task {}", task_id);
+                Ok(())
+            }
+            Some(InterruptCommand::Ls { id, date }) => {
+                let request = match id {
+                    Some(id) => Request::ListInterruptionsByTask { task_id: id },
+                    None => Request::ListInterruptionsOnDate {
+                        date: core::todo::parse_or_today(&conf.timezone, date.as_deref())?,
+                    },
+                };
+                let interruptions: Vec<core::interruption::Interruption> = e.execute(request)?;
+                for line in cli::render_rows(output, &interruptions, &conf.timezone)? {
+                    println!("{}", line);
+                }
+                Ok(())
+            }
+        },
+        Command::Task { task_command } => match task_command {
+            TaskCommand::Ls {
+                lane,
+                archived,
+                tag,
+            } => {
+                let request = match (archived, tag) {
+                    (true, _) => Request::ListArchivedTasks,
+                    (false, Some(tag)) => Request::ListTaggedTasks { lane, tag },
+                    (false, None) => Request::ListTasks { lane },
+                };
+                let tasks: Vec<core::task::Task> = e.execute(request)?;
+                let lanes: Vec<core::lane::Lane> = e.execute(Request::ListLanes)?;
+                let priorities: Vec<core::priority::Priority> =
+                    e.execute(Request::ListPriorities)?;
+                let task_tags = e.execute(Request::ListTaskTags)?;
+                let context = TaskContext::new(&lanes, &priorities).with_tags(task_tags);
+                for line in context.render(output, &tasks)? {
+                    println!("{}", line);
                 }
                 Ok(())
             }
@@ -228,8 +695,12 @@ async fn main() -> Result<()> {
                 priority,
                 estimate,
             } => {
-                let mut session = sql::Session::connect(&conf)?;
-                core::task::add_task(&mut session, &lane, &priority, &summary, estimate)?;
+                e.execute::<Id>(Request::AddTask {
+                    lane,
+                    priority,
+                    summary,
+                    estimate,
+                })?;
                 Ok(())
             }
             TaskCommand::Mod {
@@ -238,75 +709,184 @@ async fn main() -> Result<()> {
                 lane,
                 priority,
                 estimate,
-            } => {
-                let mut session = sql::Session::connect(&conf)?;
-                core::task::mod_task(
-                    &mut session,
-                    id,
-                    lane.as_deref(),
-                    priority.as_deref(),
-                    summary.as_deref(),
-                    estimate,
-                )?;
+            } => e.execute(Request::ModTask {
+                id,
+                lane,
+                priority,
+                summary,
+                estimate,
+            }),
+            TaskCommand::Rm { id } => e.execute(Request::RemoveTask { id }),
+            TaskCommand::Restore { id } => e.execute(Request::RestoreTask { id }),
+            TaskCommand::Show { id } => {
+                let detail = e.execute(Request::GetTaskDetail { id })?;
+                for line in cli::render_detail(output, &detail, &conf.timezone)? {
+                    println!("{}", line);
+                }
+                Ok(())
+            }
+            TaskCommand::Estimates { id } => {
+                let estimates: Vec<core::estimate::Estimate> =
+                    e.execute(Request::ListEstimates { task_id: id })?;
+                for line in cli::render_rows(output, &estimates, &conf.timezone)? {
+                    println!("{}", line);
+                }
                 Ok(())
             }
-            TaskCommand::Rm { id } => {
-                // TODO
+            TaskCommand::Accuracy { lane } => {
+                let report: Vec<core::estimate::Accuracy> =
+                    e.execute(Request::AccuracyReport { lane })?;
+                for line in cli::render_accuracy(output, &report, &conf.timezone)? {
+                    println!("{}", line);
+                }
+                Ok(())
+            }
+            TaskCommand::Tag { id, tag } => e.execute(Request::TagTask { id, tag }),
+            TaskCommand::Untag { id, tag } => e.execute(Request::UntagTask { id, tag }),
+            TaskCommand::Purge { older_than } => {
+                let before = Utc::now() - chrono::Duration::days(older_than);
+                let purged: Vec<Id> = e.execute(Request::PurgeTasks { before })?;
+                for id in purged {
+                    println!("purged\t{}", id);
+                }
                 Ok(())
             }
         },
         Command::Todo { todo_command } => match todo_command {
             TodoCommand::Ls { date } => {
-                let mut session = sql::Session::connect(&conf)?;
-                let date = parse_or_today(&conf.timezone, date.as_deref())?;
-                let tasks = core::todo::list_todo_tasks(&mut session, &date)?;
+                let date = core::todo::parse_or_today(&conf.timezone, date.as_deref())?;
+                let tasks: Vec<core::todo::TodoTask> =
+                    e.execute(Request::ListTodoTasks { date })?;
 
-                let estimate = tasks.iter().fold(0, |s, t| s + t.estimate);
-                let actual = tasks.iter().fold(0, |s, t| s + t.actual);
-                let remaining = estimate - actual;
-                println!(
-                    "#date:{}\testimate:{}\tactual:{}\tremaining:{}",
-                    format_date(&conf, date),
-                    estimate,
-                    actual,
-                    remaining
-                );
+                let breaks: Vec<core::breaks::Break> = e.execute(Request::ListBreaks { date })?;
+                let summary = TodoSummary::new(format_date(conf, date), &tasks, &breaks);
 
-                let lanes = core::lane::fetch_all_lanes(&mut session)?;
-                let priorities = core::priority::fetch_all_priority(&mut session)?;
-                let context = TaskContext::new(&lanes, &priorities);
-                for t in tasks {
-                    println!("{}", context.format(t));
+                let lanes: Vec<core::lane::Lane> = e.execute(Request::ListLanes)?;
+                let priorities: Vec<core::priority::Priority> =
+                    e.execute(Request::ListPriorities)?;
+                let task_tags = e.execute(Request::ListTaskTags)?;
+                let context = TaskContext::new(&lanes, &priorities).with_tags(task_tags);
+                for line in context.render_todo(output, &summary, &tasks)? {
+                    println!("{}", line);
                 }
                 Ok(())
             }
             TodoCommand::Load { date } => {
-                let mut session = sql::Session::connect(&conf)?;
-                let date = parse_or_today(&conf.timezone, date.as_deref())?;
-                let stdin = std::io::stdin();
-                let stdin = stdin.lock();
-                let ids_to_load = parse_line_as_task_ids(stdin)?;
-                let empty_removed = Vec::new();
-                core::todo::clear_todo(&mut session, &date)?;
-                core::todo::mod_todo(&mut session, &date, &ids_to_load, &empty_removed)?;
-                println!("{}", format_date(&conf, date));
+                let date = core::todo::parse_or_today(&conf.timezone, date.as_deref())?;
+                let input = std::io::read_to_string(std::io::stdin())?;
+                let task_ids = check_task_lines(e, &cli::parse_task_ids(&input)?)?;
+                e.execute::<()>(Request::ReplaceTodo { date, task_ids })?;
+                println!("{}", format_date(conf, date));
                 Ok(())
             }
-        },
-    }
-}
+            TodoCommand::Edit { date } => {
+                let date = core::todo::parse_or_today(&conf.timezone, date.as_deref())?;
+                let planned: Vec<core::todo::TodoTask> =
+                    e.execute(Request::ListTodoTasks { date })?;
+                let lanes: Vec<core::lane::Lane> = e.execute(Request::ListLanes)?;
+                let priorities: Vec<core::priority::Priority> =
+                    e.execute(Request::ListPriorities)?;
+                let task_tags = e.execute(Request::ListTaskTags)?;
+                let context = TaskContext::new(&lanes, &priorities).with_tags(task_tags);
 
-#[cfg(test)]
-mod tests {
-    use anyhow::Result;
-    use chrono::{DateTime, FixedOffset, TimeZone, Utc};
-    #[test]
-    fn test_parse_or_today() -> Result<()> {
-        let input = "2021-01-01";
-        let jst = FixedOffset::east(9 * 3600);
-        let parsed: DateTime<Utc> = super::parse_or_today(&jst, Some(input))?;
-        let expected = Utc.datetime_from_str("2020-12-31 15:00:00", "%Y-%m-%d %H:%M:%S")?;
-        assert_eq!(parsed, expected);
-        Ok(())
+                let mut content = format!(
+                    "# Todo of {}. One task per line, in the order to work on.\n\
+                     # Uncomment backlog tasks to plan them, delete lines to unplan.\n\
+                     # Only the first column is read. Empty list clears the todo.\n",
+                    format_date(conf, date)
+                );
+                for t in &planned {
+                    content.push_str(&format!("{}\n", context.format(t)));
+                }
+                // finished tasks are not worth planning
+                for l in lanes.iter().filter(|l| l.name != DONE_LANE) {
+                    let tasks: Vec<core::task::Task> = e.execute(Request::ListTasks {
+                        lane: l.name.clone(),
+                    })?;
+                    let backlog: Vec<&core::task::Task> = tasks
+                        .iter()
+                        .filter(|t| planned.iter().all(|p| p.task_id != t.id))
+                        .collect();
+                    if backlog.is_empty() {
+                        continue;
+                    }
+                    content.push_str(&format!("\n# Backlog in {}\n", l.name));
+                    for t in backlog {
+                        content.push_str(&format!("# {}\n", context.format(t)));
+                    }
+                }
+
+                let edited = cli::editor::edit(&content, ".tsv")?;
+                let task_ids = check_task_lines(e, &cli::parse_task_ids(&edited)?)?;
+                if task_ids.iter().eq(planned.iter().map(|t| &t.task_id)) {
+                    println!("unchanged\t{}", format_date(conf, date));
+                    return Ok(());
+                }
+                e.execute::<()>(Request::ReplaceTodo { date, task_ids })?;
+                println!("{}", format_date(conf, date));
+                Ok(())
+            }
+            TodoCommand::Note { date, note } => {
+                let date = core::todo::parse_or_today(&conf.timezone, date.as_deref())?;
+                match note {
+                    Some(note) => e.execute::<()>(Request::SetTodoNote { date, note })?,
+                    None => {
+                        let plan: core::todo::Plan = e.execute(Request::GetPlan { date })?;
+                        println!("{}", plan.note)
+                    }
+                }
+                Ok(())
+            }
+        },
+        Command::Lane { lane_command } => match lane_command {
+            LaneCommand::Ls {} => {
+                let lanes: Vec<core::lane::Lane> = e.execute(Request::ListLanes)?;
+                for line in cli::render_rows(output, &lanes, &conf.timezone)? {
+                    println!("{}", line);
+                }
+                Ok(())
+            }
+            LaneCommand::Add { name } => {
+                e.execute::<Id>(Request::AddLane { name })?;
+                Ok(())
+            }
+            LaneCommand::Rename { name, to } => e.execute(Request::RenameLane { name, to }),
+            LaneCommand::Rm { name, move_to } => e.execute(Request::RemoveLane { name, move_to }),
+            LaneCommand::Reorder { names } => e.execute(Request::ReorderLanes { names }),
+        },
+        Command::Priority { priority_command } => match priority_command {
+            PriorityCommand::Ls {} => {
+                let priorities: Vec<core::priority::Priority> =
+                    e.execute(Request::ListPriorities)?;
+                for line in cli::render_rows(output, &priorities, &conf.timezone)? {
+                    println!("{}", line);
+                }
+                Ok(())
+            }
+            PriorityCommand::Add { name, alias } => {
+                e.execute::<Id>(Request::AddPriority { name, alias })?;
+                Ok(())
+            }
+            PriorityCommand::Rename { name, to, alias } => {
+                e.execute(Request::RenamePriority { name, to, alias })
+            }
+            PriorityCommand::Reorder { names } => e.execute(Request::ReorderPriorities { names }),
+        },
+        Command::Tag { tag_command } => match tag_command {
+            TagCommand::Ls {} => {
+                let tags: Vec<core::tag::Tag> = e.execute(Request::ListTags)?;
+                for line in cli::render_rows(output, &tags, &conf.timezone)? {
+                    println!("{}", line);
+                }
+                Ok(())
+            }
+            TagCommand::Add { name } => e.execute(Request::AddTag { name }),
+            TagCommand::Rm { name } => e.execute(Request::RemoveTag { name }),
+        },
+        Command::Init {}
+        | Command::Server { .. }
+        | Command::Daemon {}
+        | Command::Config { .. }
+        | Command::Db { .. } => unreachable!("local command is handled in main"),
     }
 }
@@ -0,0 +1,39 @@
+use super::{Call, Execute, Failure, Request, Response, Settings, PATH};
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+
+/// Runs command on remote ly server.
+pub struct Client {
+    url: String,
+    agent: ureq::Agent,
+    settings: Settings,
+}
+
+impl Client {
+    pub fn new(base_url: &str, settings: Settings) -> Client {
+        Client {
+            url: format!("{}{}", base_url.trim_end_matches('/'), PATH),
+            agent: ureq::Agent::new(),
+            settings,
+        }
+    }
+}
+
+impl Execute for Client {
+    fn execute<T: DeserializeOwned>(&mut self, request: Request) -> Result<T> {
+        debug!("sending request to {}: {:?}", self.url, request);
+        let call = Call {
+            settings: self.settings.clone(),
+            request,
+        };
+        let response: Response = self
+            .agent
+            .post(&self.url)
+            .send_json(&call)
+            .with_context(|| format!("Failed to send request to {}", self.url))?
+            .into_json()
+            .with_context(|| format!("Failed to read response from {}", self.url))?;
+        let value = response.map_err(Failure::into_error)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
@@ -0,0 +1,446 @@
+use crate::config::Config;
+use crate::core::pomodoro::Outcome;
+use crate::core::timer::TimerType;
+use crate::core::todo::TodoDate;
+use crate::core::{
+    breaks, estimate, interruption, lane, priority, tag, task, timer, todo, Id, Repository,
+    RepositoryError,
+};
+use crate::daemon;
+use crate::sql::Session;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub mod client;
+
+/// Path of the command endpoint on server.
+pub const PATH: &str = "/rpc";
+
+/// Result of request.
+pub type Response = std::result::Result<Value, Failure>;
+
+/// Error sent as its message. [`RepositoryError`] is sent as it is
+/// so that client fails in the same way as local database.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Failure {
+    pub message: String,
+    pub error: Option<RepositoryError>,
+}
+
+impl From<anyhow::Error> for Failure {
+    fn from(e: anyhow::Error) -> Self {
+        Failure {
+            message: format!("{:#}", e),
+            error: e.downcast_ref::<RepositoryError>().cloned(),
+        }
+    }
+}
+
+impl Failure {
+    pub fn into_error(self) -> anyhow::Error {
+        match self.error {
+            Some(e) => e.into(),
+            None => anyhow!(self.message),
+        }
+    }
+}
+
+/// Part of client configuration which commands depend on.
+/// Sent along with request so that server runs command as the client would do locally.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    pub timezone: Tz,
+    pub pomodoro: i64,
+    pub short_break: i64,
+    pub long_break: i64,
+    pub long_break_interval: usize,
+}
+
+impl From<&Config> for Settings {
+    fn from(conf: &Config) -> Self {
+        Settings {
+            timezone: conf.timezone,
+            pomodoro: conf.pomodoro,
+            short_break: conf.short_break,
+            long_break: conf.long_break,
+            long_break_interval: conf.long_break_interval,
+        }
+    }
+}
+
+/// Request with settings of the client, sent as body of [`PATH`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Call {
+    pub settings: Settings,
+    pub request: Request,
+}
+
+/// Command sent from CLI client to server.
+/// Each request runs a whole command on server, so that checks and writes of
+/// a command are done under the lock of the server session at once.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Request {
+    GetTimer,
+    /// Starts pomodoro. Duration defaults to [`Settings::pomodoro`].
+    Start {
+        task_id: Id,
+        duration_min: Option<i64>,
+    },
+    /// Starts break. Type is suggested from the cycle when omitted.
+    Break {
+        timer_type: Option<TimerType>,
+        duration_min: Option<i64>,
+    },
+    /// Starts the next timer in the pomodoro cycle.
+    Next,
+    /// Stops the running timer. With `started_at`, fails unless the running timer started then.
+    Stop {
+        outcome: Outcome,
+        started_at: Option<DateTime<Utc>>,
+    },
+    Pause,
+    Resume,
+    /// Completes the running timer if it has expired.
+    Expire,
+    Interrupt {
+        external: bool,
+        note: Option<String>,
+    },
+    ListInterruptionsByTask {
+        task_id: Id,
+    },
+    ListInterruptionsOnDate {
+        date: TodoDate,
+    },
+    ListTasks {
+        lane: String,
+    },
+    ListArchivedTasks,
+    ListTaggedTasks {
+        lane: String,
+        tag: String,
+    },
+    GetTaskDetail {
+        id: Id,
+    },
+    AddTask {
+        lane: String,
+        priority: String,
+        summary: String,
+        estimate: i64,
+    },
+    ModTask {
+        id: Id,
+        lane: Option<String>,
+        priority: Option<String>,
+        summary: Option<String>,
+        estimate: Option<i64>,
+    },
+    RemoveTask {
+        id: Id,
+    },
+    RestoreTask {
+        id: Id,
+    },
+    PurgeTasks {
+        before: DateTime<Utc>,
+    },
+    ListEstimates {
+        task_id: Id,
+    },
+    AccuracyReport {
+        lane: String,
+    },
+    TagTask {
+        id: Id,
+        tag: String,
+    },
+    UntagTask {
+        id: Id,
+        tag: String,
+    },
+    ListTodoTasks {
+        date: TodoDate,
+    },
+    ListBreaks {
+        date: TodoDate,
+    },
+    GetPlan {
+        date: TodoDate,
+    },
+    EnsurePlannable {
+        task_id: Id,
+    },
+    ReplaceTodo {
+        date: TodoDate,
+        task_ids: Vec<Id>,
+    },
+    AppendTodoTask {
+        date: TodoDate,
+        task_id: Id,
+    },
+    RemoveTodoTask {
+        date: TodoDate,
+        task_id: Id,
+    },
+    SetTodoNote {
+        date: TodoDate,
+        note: String,
+    },
+    ListLanes,
+    AddLane {
+        name: String,
+    },
+    RenameLane {
+        name: String,
+        to: String,
+    },
+    RemoveLane {
+        name: String,
+        move_to: Option<String>,
+    },
+    ReorderLanes {
+        names: Vec<String>,
+    },
+    ListPriorities,
+    AddPriority {
+        name: String,
+        alias: Option<String>,
+    },
+    RenamePriority {
+        name: String,
+        to: Option<String>,
+        alias: Option<String>,
+    },
+    ReorderPriorities {
+        names: Vec<String>,
+    },
+    ListTags,
+    ListTaskTags,
+    AddTag {
+        name: String,
+    },
+    RemoveTag {
+        name: String,
+    },
+}
+
+impl Request {
+    /// Timer expired while nobody watched it is completed before the command touches timer.
+    fn expires_timer(&self) -> bool {
+        matches!(
+            self,
+            Request::Start { .. }
+                | Request::Break { .. }
+                | Request::Next
+                | Request::Stop { .. }
+                | Request::Pause
+                | Request::Resume
+        )
+    }
+}
+
+/// Runs command either on local database or on remote server.
+pub trait Execute {
+    fn execute<T: DeserializeOwned>(&mut self, request: Request) -> Result<T>;
+}
+
+/// Runs command on local database session.
+pub struct Local {
+    session: Session,
+    settings: Settings,
+}
+
+impl Local {
+    pub fn new(session: Session, settings: Settings) -> Local {
+        Local { session, settings }
+    }
+}
+
+impl Execute for Local {
+    fn execute<T: DeserializeOwned>(&mut self, request: Request) -> Result<T> {
+        let value = dispatch(&mut self.session, &self.settings, request)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+fn json<T: Serialize>(value: T) -> Result<Value> {
+    Ok(serde_json::to_value(value)?)
+}
+
+fn take_break<R>(
+    r: &mut R,
+    settings: &Settings,
+    timer_type: TimerType,
+    duration_min: Option<i64>,
+) -> Result<timer::Timer>
+where
+    R: Repository,
+{
+    let default_duration = match timer_type {
+        TimerType::LongBreak => settings.long_break,
+        _ => settings.short_break,
+    };
+    timer::take_break(r, &timer_type, duration_min.unwrap_or(default_duration))
+}
+
+/// Runs command of the request on the repository and returns its result as JSON.
+pub fn dispatch<R>(r: &mut R, settings: &Settings, request: Request) -> Result<Value>
+where
+    R: Repository,
+{
+    debug!("dispatching request: {:?}", request);
+    let tz = &settings.timezone;
+    let now = Utc::now();
+    if request.expires_timer() {
+        daemon::expire_timer(r)?;
+    }
+    match request {
+        Request::GetTimer => json(timer::get_current_timer(r)?),
+        Request::Start {
+            task_id,
+            duration_min,
+        } => json(timer::pomodoro(
+            r,
+            task_id,
+            duration_min.unwrap_or(settings.pomodoro),
+        )?),
+        Request::Break {
+            timer_type,
+            duration_min,
+        } => {
+            let timer_type = match timer_type {
+                Some(t) => t,
+                None => timer::suggest_break(r, now, tz, settings.long_break_interval)?,
+            };
+            json(take_break(r, settings, timer_type, duration_min)?)
+        }
+        Request::Next => match timer::next_in_cycle(r, now, tz, settings.long_break_interval)? {
+            timer::Next::Pomodoro(t) => json(timer::pomodoro(r, t.task_id, settings.pomodoro)?),
+            timer::Next::Break(timer_type) => json(take_break(r, settings, timer_type, None)?),
+        },
+        Request::Stop {
+            outcome,
+            started_at: None,
+        } => json(timer::stop_current(r, &outcome)?),
+        Request::Stop {
+            outcome,
+            started_at: Some(started_at),
+        } => match timer::get_current_timer(r)? {
+            Some(t) if t.started_at == started_at => {
+                timer::stop(r, &t, &outcome, now)?;
+                json(t)
+            }
+            _ => Err(RepositoryError::TimerAlreadyStopped.into()),
+        },
+        Request::Pause => json(timer::pause(r, now)?),
+        Request::Resume => json(timer::resume(r, now)?),
+        Request::Expire => json(timer::expire(r, now)?),
+        Request::Interrupt { external, note } => {
+            json(interruption::interrupt(r, external, note.as_deref())?)
+        }
+        Request::ListInterruptionsByTask { task_id } => {
+            json(interruption::list_by_task(r, task_id)?)
+        }
+        Request::ListInterruptionsOnDate { date } => {
+            json(interruption::list_on_date(r, &date, tz)?)
+        }
+        Request::ListTasks { lane } => json(task::list_all_tasks(r, &lane)?),
+        Request::ListArchivedTasks => json(task::list_archived_tasks(r)?),
+        Request::ListTaggedTasks { lane, tag } => json(tag::list_tagged_tasks(r, &lane, &tag)?),
+        Request::GetTaskDetail { id } => json(task::get_detail(r, id)?),
+        Request::AddTask {
+            lane,
+            priority,
+            summary,
+            estimate,
+        } => json(task::add_task(r, &lane, &priority, &summary, estimate)?),
+        Request::ModTask {
+            id,
+            lane,
+            priority,
+            summary,
+            estimate,
+        } => json(task::mod_task(
+            r,
+            id,
+            lane.as_deref(),
+            priority.as_deref(),
+            summary.as_deref(),
+            estimate,
+        )?),
+        Request::RemoveTask { id } => json(task::remove_task(r, id)?),
+        Request::RestoreTask { id } => json(task::restore_task(r, id)?),
+        Request::PurgeTasks { before } => json(task::purge_tasks(r, &before)?),
+        Request::ListEstimates { task_id } => json(estimate::list_estimates(r, task_id)?),
+        Request::AccuracyReport { lane } => json(estimate::accuracy_report(r, &lane)?),
+        Request::TagTask { id, tag } => json(tag::tag_task(r, id, &tag)?),
+        Request::UntagTask { id, tag } => json(tag::untag_task(r, id, &tag)?),
+        Request::ListTodoTasks { date } => json(todo::list_todo_tasks(r, &date, tz)?),
+        Request::ListBreaks { date } => json(breaks::list_on_date(r, &date, tz)?),
+        Request::GetPlan { date } => json(todo::get_plan(r, &date, tz)?),
+        Request::EnsurePlannable { task_id } => json(todo::ensure_plannable(r, task_id)?),
+        Request::ReplaceTodo { date, task_ids } => {
+            json(todo::replace_todo(r, &date, &task_ids, None)?)
+        }
+        Request::AppendTodoTask { date, task_id } => {
+            json(todo::append_todo_task(r, &date, tz, task_id)?)
+        }
+        Request::RemoveTodoTask { date, task_id } => {
+            json(todo::remove_todo_task(r, &date, tz, task_id)?)
+        }
+        Request::SetTodoNote { date, note } => json(todo::set_note(r, &date, &note)?),
+        Request::ListLanes => json(lane::fetch_all_lanes(r)?),
+        Request::AddLane { name } => json(lane::add_lane(r, &name)?),
+        Request::RenameLane { name, to } => json(lane::rename_lane(r, &name, &to)?),
+        Request::RemoveLane { name, move_to } => {
+            json(lane::remove_lane(r, &name, move_to.as_deref())?)
+        }
+        Request::ReorderLanes { names } => json(lane::reorder_lanes(r, &names)?),
+        Request::ListPriorities => json(priority::fetch_all_priority(r)?),
+        Request::AddPriority { name, alias } => {
+            json(priority::add_priority(r, &name, alias.as_deref())?)
+        }
+        Request::RenamePriority { name, to, alias } => json(priority::rename_priority(
+            r,
+            &name,
+            to.as_deref(),
+            alias.as_deref(),
+        )?),
+        Request::ReorderPriorities { names } => json(priority::reorder_priorities(r, &names)?),
+        Request::ListTags => json(tag::fetch_all_tags(r)?),
+        Request::ListTaskTags => json(tag::fetch_all_task_tags(r)?),
+        Request::AddTag { name } => json(tag::add_tag(r, &name)?),
+        Request::RemoveTag { name } => json(tag::remove_tag(r, &name)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Failure, Response};
+    use crate::core::RepositoryError;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_failure_keeps_repository_error() -> anyhow::Result<()> {
+        let sent: Response = Err(Failure::from(anyhow::Error::from(
+            RepositoryError::LaneNotFound(String::from("review")),
+        )));
+        let received: Response = serde_json::from_str(&serde_json::to_string(&sent)?)?;
+        let e = received.unwrap_err().into_error();
+        assert_eq!(
+            e.downcast_ref::<RepositoryError>(),
+            Some(&RepositoryError::LaneNotFound(String::from("review")))
+        );
+
+        let other = Failure::from(anyhow!("invalid input")).into_error();
+        assert!(other.downcast_ref::<RepositoryError>().is_none());
+        assert_eq!(other.to_string(), "invalid input");
+        Ok(())
+    }
+}
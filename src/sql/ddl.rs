@@ -6,7 +6,7 @@ const LANES: &str = "CREATE TABLE IF NOT EXISTS lanes (
  )";
 
 const LANES_INSERT: &str =
-    "INSERT INTO lanes(id, name) VALUES (1, 'backlog'), (2, 'todo'), (3, 'done')";
+    "INSERT OR IGNORE INTO lanes(id, name) VALUES (1, 'backlog'), (2, 'todo'), (3, 'done')";
 
 const PRIORITIES: &str = "CREATE TABLE IF NOT EXISTS priorities (
   id INTEGER PRIMARY KEY,
@@ -16,7 +16,7 @@ const PRIORITIES: &str = "CREATE TABLE IF NOT EXISTS priorities (
  )";
 
 const PRIORITIES_INSERT: &str =
-    "INSERT INTO priorities(id, name) VALUES (0, 'n'), (1, 'l'), (2, 'm'), (3, 'h')";
+    "INSERT OR IGNORE INTO priorities(id, name) VALUES (0, 'n'), (1, 'l'), (2, 'm'), (3, 'h')";
 
 const TASKS: &str = "CREATE TABLE IF NOT EXISTS tasks (
   id INTEGER PRIMARY KEY,
@@ -56,7 +56,7 @@ const TIMER_TYPES: &str = "CREATE TABLE IF NOT EXISTS timer_types (
  )";
 
 const TIMER_TYPES_INSERT: &str =
-    "INSERT INTO timer_types(id, name) VALUES (0, 'pomodoro'), (1, 'short break'), (2, 'long break')";
+    "INSERT OR IGNORE INTO timer_types(id, name) VALUES (0, 'pomodoro'), (1, 'short break'), (2, 'long break')";
 
 const TIMERS: &str = "CREATE TABLE IF NOT EXISTS timers (
   id INTEGER PRIMARY KEY,
@@ -123,22 +123,148 @@ const TAGGED_TASKS: &str = "CREATE TABLE IF NOT EXISTS tagged_tasks (
   FOREIGN KEY (task_id) REFERENCES tasks (id)
 )";
 
-pub const STATEMENTS: [&str; 17] = [
-    LANES,
-    LANES_INSERT,
-    PRIORITIES,
-    PRIORITIES_INSERT,
-    TASKS,
-    TIMER_TYPES,
-    TIMER_TYPES_INSERT,
-    TIMERS,
-    TIMER_TASKS,
-    TODO,
-    TODO_TASKS,
-    ESTIMATES,
-    POMODOROS,
-    BREAKS,
-    INTERRUPTIONS,
-    TAGS,
-    TAGGED_TASKS,
+const TASKS_ADD_ARCHIVED_AT: &str = "ALTER TABLE tasks ADD COLUMN archived_at TIMESTAMP";
+
+const INTERRUPTIONS_ADD_NOTE: &str = "ALTER TABLE interruptions ADD COLUMN note VARCHAR";
+
+const ESTIMATES_BACKFILL: &str = "INSERT INTO estimates(task_id, value, created_at, updated_at)
+SELECT id, estimate, updated_at, updated_at FROM tasks
+WHERE id NOT IN (SELECT task_id FROM estimates WHERE task_id IS NOT NULL)";
+
+const BREAKS_ADD_TIMER_TYPE: &str =
+    "ALTER TABLE breaks ADD COLUMN timer_type INTEGER NOT NULL DEFAULT 1 REFERENCES timer_types (id)";
+
+const BREAKS_ADD_DURATION_MIN: &str =
+    "ALTER TABLE breaks ADD COLUMN duration_min INTEGER NOT NULL DEFAULT 0";
+
+/// 0: completed, 1: voided, 2: cancelled
+const POMODOROS_ADD_OUTCOME: &str =
+    "ALTER TABLE pomodoros ADD COLUMN outcome INTEGER NOT NULL DEFAULT 0";
+
+const TIMERS_ADD_PAUSED_AT: &str = "ALTER TABLE timers ADD COLUMN paused_at TIMESTAMP";
+
+const TIMERS_ADD_PAUSED_SEC: &str =
+    "ALTER TABLE timers ADD COLUMN paused_sec INTEGER NOT NULL DEFAULT 0";
+
+const POMODOROS_ADD_PAUSED_SEC: &str =
+    "ALTER TABLE pomodoros ADD COLUMN paused_sec INTEGER NOT NULL DEFAULT 0";
+
+const BREAKS_ADD_PAUSED_SEC: &str =
+    "ALTER TABLE breaks ADD COLUMN paused_sec INTEGER NOT NULL DEFAULT 0";
+
+const LANES_ADD_LANE_ORDER: &str =
+    "ALTER TABLE lanes ADD COLUMN lane_order INTEGER NOT NULL DEFAULT 0";
+
+const LANES_BACKFILL_LANE_ORDER: &str = "UPDATE lanes SET lane_order = id";
+
+const PRIORITIES_ADD_ALIAS: &str = "ALTER TABLE priorities ADD COLUMN alias VARCHAR";
+
+const PRIORITIES_ALIAS_INDEX: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS priorities_alias ON priorities (alias)";
+
+const PRIORITIES_ADD_RANK: &str =
+    "ALTER TABLE priorities ADD COLUMN rank INTEGER NOT NULL DEFAULT 0";
+
+const PRIORITIES_BACKFILL_RANK: &str = "UPDATE priorities SET rank = id";
+
+/// Seeded single letters become aliases of full names.
+const PRIORITIES_BACKFILL_NAME: &str = "UPDATE priorities SET
+  alias = name,
+  name = CASE name WHEN 'n' THEN 'none' WHEN 'l' THEN 'low' WHEN 'm' THEN 'medium' ELSE 'high' END
+WHERE name IN ('n', 'l', 'm', 'h')";
+
+/// Versioned set of statements which brings schema from `version - 1` to `version`.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub statements: &'static [&'static str],
+}
+
+/// Schema history ordered by version.
+/// Append new migration to the tail. Never modify the one already released.
+pub const MIGRATIONS: [Migration; 11] = [
+    Migration {
+        version: 1,
+        description: "initial schema",
+        statements: &[
+            LANES,
+            LANES_INSERT,
+            PRIORITIES,
+            PRIORITIES_INSERT,
+            TASKS,
+            TIMER_TYPES,
+            TIMER_TYPES_INSERT,
+            TIMERS,
+            TIMER_TASKS,
+            TODO,
+            TODO_TASKS,
+            ESTIMATES,
+            POMODOROS,
+            BREAKS,
+            INTERRUPTIONS,
+            TAGS,
+            TAGGED_TASKS,
+        ],
+    },
+    Migration {
+        version: 2,
+        description: "archive tasks",
+        statements: &[TASKS_ADD_ARCHIVED_AT],
+    },
+    Migration {
+        version: 3,
+        description: "interruption note",
+        statements: &[INTERRUPTIONS_ADD_NOTE],
+    },
+    Migration {
+        version: 4,
+        description: "record current estimates as history",
+        statements: &[ESTIMATES_BACKFILL],
+    },
+    Migration {
+        version: 5,
+        description: "break type and planned duration",
+        statements: &[BREAKS_ADD_TIMER_TYPE, BREAKS_ADD_DURATION_MIN],
+    },
+    Migration {
+        version: 6,
+        description: "pomodoro outcome",
+        statements: &[POMODOROS_ADD_OUTCOME],
+    },
+    Migration {
+        version: 7,
+        description: "pause timer",
+        statements: &[TIMERS_ADD_PAUSED_AT, TIMERS_ADD_PAUSED_SEC],
+    },
+    Migration {
+        version: 8,
+        description: "lane order",
+        statements: &[LANES_ADD_LANE_ORDER, LANES_BACKFILL_LANE_ORDER],
+    },
+    Migration {
+        version: 9,
+        description: "priority alias and rank",
+        statements: &[
+            PRIORITIES_ADD_ALIAS,
+            PRIORITIES_ALIAS_INDEX,
+            PRIORITIES_ADD_RANK,
+            PRIORITIES_BACKFILL_RANK,
+            PRIORITIES_BACKFILL_NAME,
+        ],
+    },
+    Migration {
+        version: 10,
+        description: "paused time of pomodoro",
+        statements: &[POMODOROS_ADD_PAUSED_SEC],
+    },
+    Migration {
+        version: 11,
+        description: "paused time of break",
+        statements: &[BREAKS_ADD_PAUSED_SEC],
+    },
 ];
+
+/// Version of schema this binary expects.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
@@ -1,56 +1,156 @@
 use crate::config::Config;
+use crate::core::breaks;
+use crate::core::estimate;
+use crate::core::event;
+use crate::core::interruption;
 use crate::core::lane;
 use crate::core::meter;
 use crate::core::pomodoro;
 use crate::core::priority;
+use crate::core::tag;
 use crate::core::task;
 use crate::core::timer;
 use crate::core::todo;
-use crate::core::Id;
-use anyhow::{Context, Result};
-use chrono::{DateTime, Duration, Utc};
+use crate::core::{Id, RepositoryError};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use rusqlite::types::{ToSqlOutput, Value};
 use rusqlite::{params, Connection, Error, OptionalExtension, Result as SqlResult, Row, ToSql};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use timer::TimerType;
+use tokio::sync::broadcast;
 
 pub mod ddl;
 
 pub struct Session {
     conn: Connection,
+    events: Option<broadcast::Sender<event::Event>>,
 }
 
 impl Session {
     fn new(conn: Connection) -> Session {
-        Session { conn }
+        Session { conn, events: None }
     }
     pub fn connect(config: &Config) -> Result<Session> {
         let conn = Connection::open(&config.database)?;
         Ok(Session::new(conn))
     }
 
+    /// Sends events published by core operations on this session to `sender`.
+    pub fn with_events(self, sender: broadcast::Sender<event::Event>) -> Session {
+        Session {
+            events: Some(sender),
+            ..self
+        }
+    }
+
     pub fn initialize(&mut self) -> Result<()> {
-        for stmt in ddl::STATEMENTS.iter() {
-            self.conn
-                .execute(stmt, [])
-                .with_context(|| format!("Failed to run statement {}", stmt))?;
+        self.migrate()?;
+        Ok(())
+    }
+
+    /// Returns schema version recorded in the database file.
+    pub fn schema_version(&self) -> Result<u32> {
+        let version = self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        Ok(version)
+    }
+
+    /// Refuses database whose schema is behind this release,
+    /// which would otherwise fail later on missing columns.
+    pub fn check_schema(&self) -> Result<()> {
+        let current = self.schema_version()?;
+        let latest = ddl::latest_version();
+        if current < latest {
+            bail!(
+                "database schema version {} is older than {}. run `ly db migrate` to upgrade it",
+                current,
+                latest
+            );
         }
         Ok(())
     }
+
+    /// Returns migrations which have not been applied to the database yet.
+    pub fn pending_migrations(&self) -> Result<Vec<&'static ddl::Migration>> {
+        let current = self.schema_version()?;
+        let latest = ddl::latest_version();
+        if current > latest {
+            bail!(
+                "database schema version {} is newer than supported version {}",
+                current,
+                latest
+            );
+        }
+        Ok(ddl::MIGRATIONS
+            .iter()
+            .filter(|m| m.version > current)
+            .collect())
+    }
+
+    /// Applies pending migrations in order and returns versions applied.
+    /// Each migration runs in its own transaction together with the version bump.
+    pub fn migrate(&mut self) -> Result<Vec<u32>> {
+        let pending = self.pending_migrations()?;
+        let mut applied = Vec::with_capacity(pending.len());
+        for migration in pending {
+            let tx = self.conn.transaction()?;
+            for stmt in migration.statements.iter() {
+                tx.execute(stmt, []).with_context(|| {
+                    format!(
+                        "Failed to run statement in migration {}: {}",
+                        migration.version, stmt
+                    )
+                })?;
+            }
+            tx.pragma_update(None, "user_version", migration.version)?;
+            tx.commit()?;
+            info!(
+                "applied migration {}: {}",
+                migration.version, migration.description
+            );
+            applied.push(migration.version);
+        }
+        Ok(applied)
+    }
+}
+
+/// Reports violation of UNIQUE constraint as `duplicate` and other failures as they are.
+fn on_unique_violation(e: Error, duplicate: RepositoryError) -> anyhow::Error {
+    match e {
+        Error::SqliteFailure(ref f, _) if f.code == rusqlite::ErrorCode::ConstraintViolation => {
+            duplicate.into()
+        }
+        _ => e.into(),
+    }
+}
+
+static ADD_LANE: &str = "INSERT INTO lanes(name, lane_order) VALUES (?, ?)";
+impl lane::Add for Session {
+    fn add_lane(&mut self, name: &str, lane_order: i64) -> Result<Id> {
+        self.conn
+            .execute(ADD_LANE, params![name, lane_order])
+            .map_err(|e| on_unique_violation(e, RepositoryError::LaneAlreadyExists(name.into())))?;
+        Ok(self.conn.last_insert_rowid())
+    }
 }
 
 fn row_to_lane(row: &Row) -> SqlResult<lane::Lane> {
     Ok(lane::Lane {
         id: row.get(0)?,
         name: row.get(1)?,
-        created_at: row.get(2)?,
-        updated_at: row.get(3)?,
+        lane_order: row.get(2)?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
     })
 }
 static FETCH_LANE_BY_NAME: &str =
-    "SELECT id, name, created_at, updated_at FROM lanes WHERE name = ?";
-static FETCH_ALL_LANES: &str = "SELECT id, name, created_at, updated_at FROM lanes";
+    "SELECT id, name, lane_order, created_at, updated_at FROM lanes WHERE name = ?";
+static FETCH_ALL_LANES: &str =
+    "SELECT id, name, lane_order, created_at, updated_at FROM lanes ORDER BY lane_order, id";
 impl lane::Fetch for Session {
     fn fetch_lane_by_name(&mut self, name: &str) -> Result<Option<lane::Lane>> {
         let lane = self
@@ -61,7 +161,7 @@ impl lane::Fetch for Session {
     }
     fn fetch_all_lanes(&mut self) -> Result<Vec<lane::Lane>> {
         let mut stmt = self.conn.prepare(FETCH_ALL_LANES)?;
-        let rows = stmt.query_map(params![], |row| Ok(row_to_lane(row)?))?;
+        let rows = stmt.query_map(params![], row_to_lane)?;
         let mut results = Vec::new();
         for r in rows {
             results.push(r?);
@@ -70,27 +170,78 @@ impl lane::Fetch for Session {
     }
 }
 
+static RENAME_LANE: &str = "UPDATE lanes SET name = ?, updated_at = datetime('now') WHERE id = ?";
+static MOD_LANE_ORDER: &str =
+    "UPDATE lanes SET lane_order = ?, updated_at = datetime('now') WHERE id = ?";
+impl lane::Mod for Session {
+    fn rename_lane(&mut self, id: Id, name: &str) -> Result<()> {
+        self.conn
+            .execute(RENAME_LANE, params![name, id])
+            .map_err(|e| on_unique_violation(e, RepositoryError::LaneAlreadyExists(name.into())))?;
+        Ok(())
+    }
+    fn mod_lane_orders(&mut self, orders: &[(Id, i64)]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        for (id, lane_order) in orders {
+            tx.execute(MOD_LANE_ORDER, params![lane_order, id])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+static MOVE_LANE_TASKS: &str =
+    "UPDATE tasks SET lane_id = ?, updated_at = datetime('now') WHERE lane_id = ?";
+static DELETE_LANE: &str = "DELETE FROM lanes WHERE id = ?";
+impl lane::Remove for Session {
+    fn remove_lane(&mut self, id: Id, move_to: Option<Id>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        if let Some(to) = move_to {
+            tx.execute(MOVE_LANE_TASKS, params![to, id])?;
+        }
+        tx.execute(DELETE_LANE, params![id])?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+static ADD_PRIORITY: &str = "INSERT INTO priorities(name, alias, rank) VALUES (?, ?, ?)";
+impl priority::Add for Session {
+    fn add_priority(&mut self, name: &str, alias: Option<&str>, rank: i64) -> Result<Id> {
+        self.conn
+            .execute(ADD_PRIORITY, params![name, alias, rank])
+            .map_err(|e| {
+                on_unique_violation(e, RepositoryError::PriorityAlreadyExists(name.into()))
+            })?;
+        Ok(self.conn.last_insert_rowid())
+    }
+}
+
 fn row_to_priority(row: &Row) -> SqlResult<priority::Priority> {
     Ok(priority::Priority {
         id: row.get(0)?,
         name: row.get(1)?,
-        created_at: row.get(2)?,
-        updated_at: row.get(3)?,
+        alias: row.get(2)?,
+        rank: row.get(3)?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
     })
 }
-static FETCH_PRIORITY_BY_NAME: &str =
-    "SELECT id, name, created_at, updated_at FROM priorities WHERE name = ?";
-static FETCH_ALL_PRIORITY: &str = "SELECT id, name, created_at, updated_at FROM priorities";
+// exact name wins over alias of other priority
+static FETCH_PRIORITY_BY_NAME: &str = "SELECT id, name, alias, rank, created_at, updated_at FROM priorities WHERE name = ?1 OR alias = ?1 ORDER BY name = ?1 DESC LIMIT 1";
+static FETCH_ALL_PRIORITY: &str =
+    "SELECT id, name, alias, rank, created_at, updated_at FROM priorities ORDER BY rank DESC, id DESC";
 impl priority::Fetch for Session {
-    fn fetch_priority_by_name(&mut self, name: &str) -> Result<priority::Priority> {
-        self.conn
-            .query_row_and_then(FETCH_PRIORITY_BY_NAME, params![name], |row| {
-                Ok(row_to_priority(row)?)
-            })
+    fn fetch_priority_by_name(&mut self, name: &str) -> Result<Option<priority::Priority>> {
+        let prio = self
+            .conn
+            .query_row(FETCH_PRIORITY_BY_NAME, params![name], row_to_priority)
+            .optional()?;
+        Ok(prio)
     }
     fn fetch_all_priority(&mut self) -> Result<Vec<priority::Priority>> {
         let mut stmt = self.conn.prepare(FETCH_ALL_PRIORITY)?;
-        let rows = stmt.query_map(params![], |row| Ok(row_to_priority(row)?))?;
+        let rows = stmt.query_map(params![], row_to_priority)?;
         let mut results = Vec::new();
         for r in rows {
             results.push(r?);
@@ -99,13 +250,62 @@ impl priority::Fetch for Session {
     }
 }
 
+static RENAME_PRIORITY: &str =
+    "UPDATE priorities SET name = ?, alias = ?, updated_at = datetime('now') WHERE id = ?";
+static MOD_PRIORITY_RANK: &str =
+    "UPDATE priorities SET rank = ?, updated_at = datetime('now') WHERE id = ?";
+impl priority::Mod for Session {
+    fn rename_priority(&mut self, id: Id, name: &str, alias: Option<&str>) -> Result<()> {
+        self.conn
+            .execute(RENAME_PRIORITY, params![name, alias, id])
+            .map_err(|e| {
+                on_unique_violation(e, RepositoryError::PriorityAlreadyExists(name.into()))
+            })?;
+        Ok(())
+    }
+    fn mod_priority_ranks(&mut self, ranks: &[(Id, i64)]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        for (id, rank) in ranks {
+            tx.execute(MOD_PRIORITY_RANK, params![rank, id])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
 static ADD_TASK: &str =
     "INSERT INTO tasks(lane_id, priority, summary, estimate) VALUES (?, ?, ?, ?)";
+static ADD_ESTIMATE: &str = "INSERT INTO estimates(task_id, value) VALUES (?, ?)";
 impl task::Add for Session {
-    fn add_task(&mut self, lane_id: Id, priority: Id, summary: &str, estimate: i64) -> Result<()> {
-        self.conn
-            .execute(ADD_TASK, params![lane_id, priority, summary, estimate])?;
-        Ok(())
+    fn add_task(&mut self, lane_id: Id, priority: Id, summary: &str, estimate: i64) -> Result<Id> {
+        let tx = self.conn.transaction()?;
+        tx.execute(ADD_TASK, params![lane_id, priority, summary, estimate])?;
+        let id = tx.last_insert_rowid();
+        tx.execute(ADD_ESTIMATE, params![id, estimate])?;
+        tx.commit()?;
+        Ok(id)
+    }
+}
+
+fn row_to_estimate(row: &Row) -> SqlResult<estimate::Estimate> {
+    Ok(estimate::Estimate {
+        id: row.get(0)?,
+        task_id: row.get(1)?,
+        value: row.get(2)?,
+        created_at: row.get(3)?,
+    })
+}
+static FETCH_ESTIMATES_BY_TASK_ID: &str =
+    "SELECT id, task_id, value, created_at FROM estimates WHERE task_id = ? ORDER BY id";
+impl estimate::Fetch for Session {
+    fn fetch_estimates_by_task_id(&mut self, task_id: Id) -> Result<Vec<estimate::Estimate>> {
+        let mut stmt = self.conn.prepare(FETCH_ESTIMATES_BY_TASK_ID)?;
+        let rows = stmt.query_map(params![task_id], row_to_estimate)?;
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
     }
 }
 
@@ -118,11 +318,13 @@ fn row_to_task(row: &Row) -> SqlResult<task::Task> {
         estimate: row.get(4)?,
         created_at: row.get(5)?,
         updated_at: row.get(6)?,
+        archived_at: row.get(7)?,
     })
 }
 
-static FETCH_TASK_BY_ID: &str = "SELECT id, lane_id, priority, summary, estimate, created_at, updated_at FROM tasks WHERE id = ?";
-static FETCH_ALL_TASKS: &str = "SELECT id, lane_id, priority, summary, estimate, created_at, updated_at FROM tasks WHERE EXISTS (SELECT id FROM lanes WHERE name = ? AND lanes.id = tasks.lane_id) ORDER BY priority DESC";
+static FETCH_TASK_BY_ID: &str = "SELECT id, lane_id, priority, summary, estimate, created_at, updated_at, archived_at FROM tasks WHERE id = ?";
+static FETCH_ALL_TASKS: &str = "SELECT id, lane_id, priority, summary, estimate, created_at, updated_at, archived_at FROM tasks WHERE archived_at IS NULL AND EXISTS (SELECT id FROM lanes WHERE name = ? AND lanes.id = tasks.lane_id) ORDER BY (SELECT rank FROM priorities WHERE priorities.id = tasks.priority) DESC, id";
+static FETCH_ARCHIVED_TASKS: &str = "SELECT id, lane_id, priority, summary, estimate, created_at, updated_at, archived_at FROM tasks WHERE archived_at IS NOT NULL ORDER BY archived_at DESC";
 impl task::Fetch for Session {
     fn fetch_task_by_id(&mut self, id: Id) -> Result<Option<task::Task>> {
         let t = self
@@ -143,6 +345,61 @@ impl task::Fetch for Session {
         }
         Ok(results)
     }
+    fn fetch_archived_tasks(&mut self) -> Result<Vec<task::Task>> {
+        let mut stmt = self.conn.prepare(FETCH_ARCHIVED_TASKS)?;
+        let rows = stmt.query_map([], row_to_task)?;
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+}
+
+static ARCHIVE_TASK: &str =
+    "UPDATE tasks SET archived_at = datetime('now'), updated_at = datetime('now') WHERE id = ?";
+static RESTORE_TASK: &str =
+    "UPDATE tasks SET archived_at = NULL, updated_at = datetime('now') WHERE id = ?";
+static FETCH_PURGEABLE_TASK_IDS: &str = "SELECT id FROM tasks
+WHERE archived_at IS NOT NULL AND archived_at < datetime(?)
+AND id NOT IN (SELECT task_id FROM timer_tasks)";
+static PURGE_TASK_DEPENDENTS: [&str; 5] = [
+    "DELETE FROM todo_tasks WHERE task_id = ?",
+    "DELETE FROM pomodoros WHERE task_id = ?",
+    "DELETE FROM tagged_tasks WHERE task_id = ?",
+    "DELETE FROM estimates WHERE task_id = ?",
+    "DELETE FROM interruptions WHERE task_id = ?",
+];
+static PURGE_TASK: &str = "DELETE FROM tasks WHERE id = ?";
+impl task::Remove for Session {
+    fn archive_task(&mut self, id: Id) -> Result<()> {
+        self.conn.execute(ARCHIVE_TASK, params![id])?;
+        Ok(())
+    }
+    fn restore_task(&mut self, id: Id) -> Result<()> {
+        self.conn.execute(RESTORE_TASK, params![id])?;
+        Ok(())
+    }
+    fn purge_tasks(&mut self, before: &DateTime<Utc>) -> Result<Vec<Id>> {
+        let tx = self.conn.transaction()?;
+        let ids = {
+            let mut stmt = tx.prepare(FETCH_PURGEABLE_TASK_IDS)?;
+            let rows = stmt.query_map(params![before], |row| row.get(0))?;
+            let mut ids: Vec<Id> = Vec::new();
+            for r in rows {
+                ids.push(r?);
+            }
+            ids
+        };
+        for id in ids.iter() {
+            for stmt in PURGE_TASK_DEPENDENTS.iter() {
+                tx.execute(stmt, params![id])?;
+            }
+            tx.execute(PURGE_TASK, params![id])?;
+        }
+        tx.commit()?;
+        Ok(ids)
+    }
 }
 
 static MOD_TASK: &str = "UPDATE tasks SET lane_id = ?, priority = ?, summary = ?, estimate = ?, updated_at = datetime('now') WHERE id = ?";
@@ -155,17 +412,114 @@ impl task::Mod for Session {
         summary: Option<&str>,
         estimate: Option<i64>,
     ) -> Result<()> {
-        let old = self
-            .conn
-            .query_row_and_then(FETCH_TASK_BY_ID, params![id], |row| row_to_task(row))?;
+        let tx = self.conn.transaction()?;
+        let old = tx
+            .query_row_and_then(FETCH_TASK_BY_ID, params![id], row_to_task)
+            .optional()?
+            .ok_or(RepositoryError::TaskNotFound(id))?;
         let set_lane_id = lane_id.unwrap_or(&old.lane_id);
         let set_priority = priority.unwrap_or(&old.priority);
         let set_summary = summary.unwrap_or(&old.summary);
         let set_estimate = estimate.unwrap_or(old.estimate);
-        self.conn.execute(
+        tx.execute(
             MOD_TASK,
             params![set_lane_id, set_priority, set_summary, set_estimate, id],
         )?;
+        // keep history of re-estimation
+        if set_estimate != old.estimate {
+            tx.execute(ADD_ESTIMATE, params![id, set_estimate])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/* ---------------------------------------------------------------
+ * tag
+ * ---------------------------------------------------------------
+ */
+static ADD_TAG: &str = "INSERT INTO tags(name) VALUES (?)";
+impl tag::Add for Session {
+    fn add_tag(&mut self, name: &str) -> Result<()> {
+        self.conn
+            .execute(ADD_TAG, params![name])
+            .map_err(|e| on_unique_violation(e, RepositoryError::TagAlreadyExists(name.into())))?;
+        Ok(())
+    }
+}
+
+fn row_to_tag(row: &Row) -> SqlResult<tag::Tag> {
+    Ok(tag::Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        created_at: row.get(2)?,
+        updated_at: row.get(3)?,
+    })
+}
+static FETCH_TAG_BY_NAME: &str = "SELECT id, name, created_at, updated_at FROM tags WHERE name = ?";
+static FETCH_ALL_TAGS: &str = "SELECT id, name, created_at, updated_at FROM tags ORDER BY name";
+static FETCH_ALL_TASK_TAGS: &str = "SELECT tagged.task_id, tagged.tag_id, tag.name
+FROM tagged_tasks tagged
+JOIN tags tag ON tag.id = tagged.tag_id
+ORDER BY tagged.task_id, tag.name";
+impl tag::Fetch for Session {
+    fn fetch_tag_by_name(&mut self, name: &str) -> Result<Option<tag::Tag>> {
+        let t = self
+            .conn
+            .query_row(FETCH_TAG_BY_NAME, params![name], row_to_tag)
+            .optional()?;
+        Ok(t)
+    }
+    fn fetch_all_tags(&mut self) -> Result<Vec<tag::Tag>> {
+        let mut stmt = self.conn.prepare(FETCH_ALL_TAGS)?;
+        let rows = stmt.query_map([], row_to_tag)?;
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+    fn fetch_all_task_tags(&mut self) -> Result<Vec<tag::TaskTag>> {
+        let mut stmt = self.conn.prepare(FETCH_ALL_TASK_TAGS)?;
+        let rows = stmt.query_map([], |row| {
+            Ok(tag::TaskTag {
+                task_id: row.get(0)?,
+                tag_id: row.get(1)?,
+                name: row.get(2)?,
+            })
+        })?;
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+}
+
+static DELETE_TAGGED_TASKS_BY_TAG: &str = "DELETE FROM tagged_tasks WHERE tag_id = ?";
+static DELETE_TAG: &str = "DELETE FROM tags WHERE id = ?";
+impl tag::Remove for Session {
+    fn remove_tag(&mut self, id: Id) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(DELETE_TAGGED_TASKS_BY_TAG, params![id])?;
+        tx.execute(DELETE_TAG, params![id])?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+static ATTACH_TAG: &str = "INSERT OR IGNORE INTO tagged_tasks(tag_id, task_id) VALUES (?, ?)";
+static DETACH_TAG: &str = "DELETE FROM tagged_tasks WHERE tag_id = ? AND task_id = ?";
+impl tag::Attach for Session {
+    fn attach_tag(&mut self, tag_id: Id, task_id: Id) -> Result<()> {
+        self.conn.execute(ATTACH_TAG, params![tag_id, task_id])?;
+        Ok(())
+    }
+}
+
+impl tag::Detach for Session {
+    fn detach_tag(&mut self, tag_id: Id, task_id: Id) -> Result<()> {
+        self.conn.execute(DETACH_TAG, params![tag_id, task_id])?;
         Ok(())
     }
 }
@@ -183,11 +537,13 @@ fn row_to_timer(row: &Row) -> SqlResult<timer::Timer> {
         label: row.get(2)?,
         started_at: row.get(3)?,
         duration_min: row.get(4)?,
+        paused_at: row.get(5)?,
+        paused_sec: row.get(6)?,
     })
 }
 
 impl ToSql for timer::TimerType {
-    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
         Ok(ToSqlOutput::Owned(Value::Integer(*self as i64)))
     }
 }
@@ -195,20 +551,6 @@ impl ToSql for timer::TimerType {
 static INSERT_TIMER_TASK: &str = "INSERT INTO timer_tasks(timer_id, task_id) VALUES (0, ?)";
 static DELETE_TIMER_TASK: &str = "DELETE FROM timer_tasks WHERE timer_id = 0";
 static GET_TIMER_TASK: &str = "SELECT timer_id, task_id FROM timer_tasks WHERE timer_id = 0";
-impl timer::TimerTaskAdd for Session {
-    fn add_timer_task(&mut self, task_id: &Id) -> Result<()> {
-        self.conn.execute(INSERT_TIMER_TASK, params![task_id])?;
-        Ok(())
-    }
-}
-
-impl timer::TimerTaskRemove for Session {
-    fn remove_timer_task(&mut self) -> Result<()> {
-        self.conn.execute(DELETE_TIMER_TASK, [])?;
-        Ok(())
-    }
-}
-
 impl timer::TimerTaskGet for Session {
     fn get_timer_task(&mut self) -> Result<Option<timer::TimerTask>> {
         let timer_task = self
@@ -224,24 +566,81 @@ impl timer::TimerTaskGet for Session {
     }
 }
 
-static START: &str = "INSERT INTO timers(id, timer_type, label, duration_min) VALUES (0, ?, ?, ?)";
-static COMPLETE: &str = "DELETE FROM timers WHERE id = 0";
-static GET_TIMER: &str =
-    "SELECT id, timer_type, label, started_at, duration_min FROM timers WHERE id = 0";
+// started_at keeps milliseconds to tell timers started within the same second apart
+static START: &str = "INSERT INTO timers(id, timer_type, label, started_at, duration_min) VALUES (0, ?, ?, strftime('%Y-%m-%d %H:%M:%f', 'now'), ?)";
+// running timer is identified by its start so that a timer stopped by other process is not stopped twice
+static FINISH: &str = "DELETE FROM timers WHERE id = 0 AND julianday(started_at) = julianday(?)";
+static ADD_TIMER_POMODORO: &str = "INSERT INTO pomodoros(task_id, started_at, finished_at, outcome, paused_sec) SELECT task_id, ?, ?, ?, ? FROM timer_tasks WHERE timer_id = 0";
+static GET_TIMER: &str = "SELECT id, timer_type, label, started_at, duration_min, paused_at, paused_sec FROM timers WHERE id = 0";
+static PAUSE: &str = "UPDATE timers SET paused_at = ? WHERE id = 0";
+static RESUME: &str = "UPDATE timers SET paused_at = NULL, paused_sec = ? WHERE id = 0";
 impl timer::Lifecycle for Session {
     fn start(
         &mut self,
         timer_type: &timer::TimerType,
         label: &str,
         duration_min: i64,
+        task_id: Option<Id>,
     ) -> Result<timer::Timer> {
-        self.conn
-            .execute(START, params![timer_type, label, duration_min])?;
-        let c = self.conn.query_row(GET_TIMER, [], row_to_timer)?;
+        let tx = self.conn.transaction()?;
+        tx.execute(START, params![timer_type, label, duration_min])
+            .map_err(|e| on_unique_violation(e, RepositoryError::TimerAlreadyRunning))?;
+        if let Some(task_id) = task_id {
+            tx.execute(INSERT_TIMER_TASK, params![task_id])?;
+        }
+        let c = tx.query_row(GET_TIMER, [], row_to_timer)?;
+        tx.commit()?;
         Ok(c)
     }
-    fn complete(&mut self) -> Result<()> {
-        self.conn.execute(COMPLETE, [])?;
+    fn finish(
+        &mut self,
+        timer: &timer::Timer,
+        outcome: &pomodoro::Outcome,
+        finished_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        // recorded before the timer is deleted as deleting it cascades to timer_tasks
+        let recorded = match (outcome, timer.timer_type) {
+            // only completed break is rest taken
+            (pomodoro::Outcome::Cancelled, _)
+            | (pomodoro::Outcome::Voided, TimerType::ShortBreak | TimerType::LongBreak) => 1,
+            (_, TimerType::Pomodoro) => tx.execute(
+                ADD_TIMER_POMODORO,
+                params![
+                    timer.started_at,
+                    finished_at,
+                    outcome,
+                    timer.paused(finished_at).num_seconds()
+                ],
+            )?,
+            (_, TimerType::ShortBreak | TimerType::LongBreak) => tx.execute(
+                ADD_BREAK,
+                params![
+                    timer.timer_type,
+                    timer.started_at,
+                    finished_at,
+                    timer.duration_min,
+                    timer.paused(finished_at).num_seconds()
+                ],
+            )?,
+        };
+        tx.execute(DELETE_TIMER_TASK, [])?;
+        if tx.execute(FINISH, params![timer.started_at])? == 0 {
+            // rolled back on drop
+            return Ok(false);
+        }
+        if recorded == 0 {
+            return Err(RepositoryError::TimerTaskNotFound.into());
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+    fn pause(&mut self, paused_at: DateTime<Utc>) -> Result<()> {
+        self.conn.execute(PAUSE, params![paused_at])?;
+        Ok(())
+    }
+    fn resume(&mut self, paused_sec: i64) -> Result<()> {
+        self.conn.execute(RESUME, params![paused_sec])?;
         Ok(())
     }
 }
@@ -262,23 +661,90 @@ fn row_to_pomodoro(row: &Row) -> SqlResult<pomodoro::Pomodoro> {
         task_id: row.get(1)?,
         started_at: row.get(2)?,
         finished_at: row.get(3)?,
+        outcome: {
+            let int_val: u8 = row.get(4)?;
+            pomodoro::Outcome::try_from(int_val).map_err(|e| {
+                Error::FromSqlConversionFailure(4, rusqlite::types::Type::Integer, Box::new(e))
+            })?
+        },
+        paused_sec: row.get(5)?,
     })
 }
 
-static ADD_POMODORO: &str = "INSERT INTO pomodoros(task_id, started_at) VALUES (?, ?)";
-impl pomodoro::Complete for Session {
-    fn complete_pomodoro(&mut self, task_id: Id, started_at: DateTime<Utc>) -> Result<()> {
-        self.conn
-            .execute(ADD_POMODORO, params![task_id, started_at])?;
-        Ok(())
+impl ToSql for pomodoro::Outcome {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::Owned(Value::Integer(u8::from(*self) as i64)))
     }
 }
 
-static FETCH_POMODOROS_BY_TASK_ID: &str = "SELECT id, task_id, started_at, finished_at FROM pomodoros WHERE task_id = ? ORDER BY started_at";
+static FETCH_POMODOROS_BY_TASK_ID: &str = "SELECT id, task_id, started_at, finished_at, outcome, paused_sec FROM pomodoros WHERE task_id = ? ORDER BY started_at";
+static FETCH_POMODOROS_BETWEEN: &str = "SELECT id, task_id, started_at, finished_at, outcome, paused_sec FROM pomodoros WHERE ? <= started_at AND started_at < ? ORDER BY started_at";
+static COUNT_COMPLETED_POMODOROS: &str =
+    "SELECT task_id, COUNT(*) FROM pomodoros WHERE outcome = 0 GROUP BY task_id";
 impl pomodoro::Fetch for Session {
     fn fetch_by_task_id(&mut self, task_id: Id) -> Result<Vec<pomodoro::Pomodoro>> {
         let mut stmt = self.conn.prepare(FETCH_POMODOROS_BY_TASK_ID)?;
-        let rows = stmt.query_map(params![task_id], |row| row_to_pomodoro(row))?;
+        let rows = stmt.query_map(params![task_id], row_to_pomodoro)?;
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+    fn fetch_pomodoros_between(
+        &mut self,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Result<Vec<pomodoro::Pomodoro>> {
+        let mut stmt = self.conn.prepare(FETCH_POMODOROS_BETWEEN)?;
+        let rows = stmt.query_map(params![start, end], row_to_pomodoro)?;
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+    fn fetch_completed_counts(&mut self) -> Result<HashMap<Id, usize>> {
+        let mut stmt = self.conn.prepare(COUNT_COMPLETED_POMODOROS)?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?;
+        let mut results = HashMap::new();
+        for r in rows {
+            let (task_id, count) = r?;
+            results.insert(task_id, count);
+        }
+        Ok(results)
+    }
+}
+
+/* ---------------------------------------------------------------
+ * break
+ * ---------------------------------------------------------------
+ */
+static ADD_BREAK: &str = "INSERT INTO breaks(timer_type, started_at, finished_at, duration_min, paused_sec) VALUES (?, ?, ?, ?, ?)";
+fn row_to_break(row: &Row) -> SqlResult<breaks::Break> {
+    Ok(breaks::Break {
+        id: row.get(0)?,
+        timer_type: {
+            let int_val: u8 = row.get(1)?;
+            TimerType::try_from(int_val).map_err(|e| {
+                Error::FromSqlConversionFailure(1, rusqlite::types::Type::Integer, Box::new(e))
+            })?
+        },
+        started_at: row.get(2)?,
+        finished_at: row.get(3)?,
+        duration_min: row.get(4)?,
+        paused_sec: row.get(5)?,
+    })
+}
+static FETCH_BREAKS_BETWEEN: &str = "SELECT id, timer_type, started_at, finished_at, duration_min, paused_sec FROM breaks WHERE ? <= started_at AND started_at < ? ORDER BY started_at";
+impl breaks::Fetch for Session {
+    fn fetch_breaks_between(
+        &mut self,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Result<Vec<breaks::Break>> {
+        let mut stmt = self.conn.prepare(FETCH_BREAKS_BETWEEN)?;
+        let rows = stmt.query_map(params![start, end], row_to_break)?;
         let mut results = Vec::new();
         for r in rows {
             results.push(r?);
@@ -287,6 +753,81 @@ impl pomodoro::Fetch for Session {
     }
 }
 
+/* ---------------------------------------------------------------
+ * interruption
+ * ---------------------------------------------------------------
+ */
+static ADD_INTERRUPTION: &str =
+    "INSERT INTO interruptions(task_id, external, note, created_at) VALUES (?, ?, ?, ?)";
+impl interruption::Add for Session {
+    fn add_interruption(
+        &mut self,
+        task_id: Id,
+        external: bool,
+        note: Option<&str>,
+        created_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.conn.execute(
+            ADD_INTERRUPTION,
+            params![task_id, external, note, created_at],
+        )?;
+        Ok(())
+    }
+}
+
+fn row_to_interruption(row: &Row) -> SqlResult<interruption::Interruption> {
+    Ok(interruption::Interruption {
+        id: row.get(0)?,
+        task_id: row.get(1)?,
+        external: row.get(2)?,
+        note: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+static FETCH_INTERRUPTIONS_BY_TASK_ID: &str = "SELECT id, task_id, external, note, created_at FROM interruptions WHERE task_id = ? ORDER BY created_at";
+static FETCH_INTERRUPTIONS_BETWEEN: &str = "SELECT id, task_id, external, note, created_at FROM interruptions WHERE ? <= created_at AND created_at < ? ORDER BY created_at";
+impl interruption::Fetch for Session {
+    fn fetch_interruptions_by_task_id(
+        &mut self,
+        task_id: Id,
+    ) -> Result<Vec<interruption::Interruption>> {
+        let mut stmt = self.conn.prepare(FETCH_INTERRUPTIONS_BY_TASK_ID)?;
+        let rows = stmt.query_map(params![task_id], row_to_interruption)?;
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+    fn fetch_interruptions_between(
+        &mut self,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Result<Vec<interruption::Interruption>> {
+        let mut stmt = self.conn.prepare(FETCH_INTERRUPTIONS_BETWEEN)?;
+        let rows = stmt.query_map(params![start, end], row_to_interruption)?;
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+}
+
+/* ---------------------------------------------------------------
+ * event
+ * ---------------------------------------------------------------
+ */
+impl event::Publish for Session {
+    fn publish(&mut self, event: &event::Event) -> Result<()> {
+        if let Some(sender) = &self.events {
+            // fails only when nobody is subscribing
+            let _ = sender.send(event.clone());
+        }
+        Ok(())
+    }
+}
+
 /* ---------------------------------------------------------------
  * todo
  * ---------------------------------------------------------------
@@ -315,6 +856,8 @@ fn row_to_todo_task(row: &Row) -> SqlResult<todo::TodoTask> {
         summary: row.get(4)?,
         estimate: row.get(5)?,
         actual: row.get(6)?,
+        internal_interruptions: row.get(7)?,
+        external_interruptions: row.get(8)?,
     })
 }
 static FETCH_TODO_BY_DATE: &str =
@@ -326,7 +869,9 @@ static FETCH_TODO_TASKS: &str = "SELECT
     task.priority AS priority,
     task.summary AS summary,
     task.estimate AS estimate,
-    CASE WHEN result.actual IS NULL THEN 0 ELSE result.actual END AS actual
+    CASE WHEN result.actual IS NULL THEN 0 ELSE result.actual END AS actual,
+    CASE WHEN interrupted.internal IS NULL THEN 0 ELSE interrupted.internal END AS internal,
+    CASE WHEN interrupted.external IS NULL THEN 0 ELSE interrupted.external END AS external
 FROM tasks task
 JOIN todo_tasks todo ON task.id = todo.task_id
 LEFT JOIN (
@@ -334,12 +879,23 @@ LEFT JOIN (
         task_id AS task_id,
         COUNT(task_id) AS actual
     FROM pomodoros
-    WHERE (started_at >= ? AND started_at < ?)
+    WHERE (started_at >= ? AND started_at < ?) AND outcome = 0
     GROUP BY task_id
 ) result ON task.id = result.task_id
-WHERE todo.date = ?
+LEFT JOIN (
+    SELECT
+        task_id AS task_id,
+        SUM(CASE WHEN external THEN 0 ELSE 1 END) AS internal,
+        SUM(CASE WHEN external THEN 1 ELSE 0 END) AS external
+    FROM interruptions
+    WHERE (created_at >= ? AND created_at < ?)
+    GROUP BY task_id
+) interrupted ON task.id = interrupted.task_id
+WHERE todo.date = ? AND task.archived_at IS NULL
 ORDER BY todo.todo_order
 ";
+static FETCH_TODO_DATES_BY_TASK_ID: &str =
+    "SELECT date FROM todo_tasks WHERE task_id = ? ORDER BY date";
 impl todo::Fetch for Session {
     fn fetch_by_date(&mut self, date: &todo::TodoDate) -> Result<Option<todo::Todo>> {
         let result = self
@@ -348,11 +904,22 @@ impl todo::Fetch for Session {
             .optional()?;
         Ok(result)
     }
-    fn fetch_todo_tasks(&mut self, date: &todo::TodoDate) -> Result<Vec<todo::TodoTask>> {
-        let start_time = *date;
-        let end_time = start_time + Duration::days(1);
+    fn fetch_todo_tasks(
+        &mut self,
+        date: &todo::TodoDate,
+        end: &todo::TodoDate,
+    ) -> Result<Vec<todo::TodoTask>> {
         let mut stmt = self.conn.prepare(FETCH_TODO_TASKS)?;
-        let rows = stmt.query_map(params![start_time, end_time, date], |r| row_to_todo_task(r))?;
+        let rows = stmt.query_map(params![date, end, date, end, date], row_to_todo_task)?;
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+    fn fetch_todo_dates_by_task_id(&mut self, task_id: Id) -> Result<Vec<todo::TodoDate>> {
+        let mut stmt = self.conn.prepare(FETCH_TODO_DATES_BY_TASK_ID)?;
+        let rows = stmt.query_map(params![task_id], |row| row.get(0))?;
         let mut results = Vec::new();
         for r in rows {
             results.push(r?);
@@ -364,6 +931,10 @@ impl todo::Fetch for Session {
 static INSERT_TODO_TASK: &str =
     "INSERT INTO todo_tasks(date, task_id, todo_order) VALUES (?, ?, ?)";
 static DELETE_TODO_TASK: &str = "DELETE FROM todo_tasks WHERE date = ? AND task_id = ?";
+static MOD_TODO_NOTE: &str =
+    "UPDATE todo SET note = ?, updated_at = datetime('now') WHERE date = ?";
+static ENSURE_TODO: &str = "INSERT OR IGNORE INTO todo(date, note) VALUES (?, '')";
+static CLEAR_TODO_TASKS: &str = "DELETE FROM todo_tasks WHERE date = ?";
 impl todo::Mod for Session {
     fn add_todo_task(
         &mut self,
@@ -382,33 +953,92 @@ impl todo::Mod for Session {
             .execute(DELETE_TODO_TASK, params![date, task_id])?;
         Ok(())
     }
+    fn mod_todo_note(&mut self, date: &todo::TodoDate, note: &str) -> Result<()> {
+        self.conn.execute(MOD_TODO_NOTE, params![note, date])?;
+        Ok(())
+    }
+    fn replace_todo_tasks(
+        &mut self,
+        date: &todo::TodoDate,
+        task_ids: &[Id],
+        note: Option<&str>,
+    ) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(ENSURE_TODO, params![date])?;
+        tx.execute(CLEAR_TODO_TASKS, params![date])?;
+        for (i, task_id) in task_ids.iter().enumerate() {
+            tx.execute(INSERT_TODO_TASK, params![date, task_id, (i as i64)])?;
+        }
+        if let Some(note) = note {
+            tx.execute(MOD_TODO_NOTE, params![note, date])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
 }
 
-// TODO: timezone offset as parameters
-static FETCH_POMODORO_DAILY: &str = "
-SELECT
-    datetime(date(started_at, '+09:00')) AS time,
-    COUNT(*) AS value
+static FETCH_POMODORO_STARTED_AT: &str = "
+SELECT started_at
 FROM pomodoros
-WHERE ? <= started_at AND started_at < ?
-GROUP BY datetime(date(started_at, '+09:00'))";
-fn row_to_measurement(row: &Row) -> SqlResult<meter::Measurement> {
-    Ok(meter::Measurement::new(row.get(0)?, row.get(1)?))
-}
+WHERE ? <= started_at AND started_at < ? AND outcome = 0
+ORDER BY started_at";
+static FETCH_INTERRUPTION_CREATED_AT: &str = "
+SELECT created_at
+FROM interruptions
+WHERE ? <= created_at AND created_at < ? AND external = ?
+ORDER BY created_at";
 impl meter::MeterQuery for Session {
-    fn query_pomodoro_daily(&mut self, range: &meter::TimeRange) -> Result<meter::Measurements> {
-        let mut stmt = self.conn.prepare(FETCH_POMODORO_DAILY)?;
-        let rows = stmt.query_map(params![range.start, range.end], |row| {
-            Ok(row_to_measurement(row)?)
-        })?;
-        let mut results = Vec::new();
+    fn query_pomodoro_daily(
+        &mut self,
+        range: &meter::TimeRange,
+        timezone: &Tz,
+    ) -> Result<meter::Measurements> {
+        let mut stmt = self.conn.prepare(FETCH_POMODORO_STARTED_AT)?;
+        let rows = stmt.query_map(params![range.start, range.end], |row| row.get(0))?;
+        let mut started = Vec::new();
         for r in rows {
-            results.push(r?);
+            started.push(r?);
         }
         Ok(meter::Measurements {
             instrument: meter::Instrument::PomodoroDaily,
             labels: HashMap::new(),
-            data: results,
+            data: meter::count_daily(&started, timezone),
+        })
+    }
+    fn query_interruption_daily(
+        &mut self,
+        range: &meter::TimeRange,
+        timezone: &Tz,
+        external: bool,
+    ) -> Result<meter::Measurements> {
+        let mut stmt = self.conn.prepare(FETCH_INTERRUPTION_CREATED_AT)?;
+        let rows = stmt.query_map(params![range.start, range.end, external], |row| row.get(0))?;
+        let mut created = Vec::new();
+        for r in rows {
+            created.push(r?);
+        }
+        let mut labels = HashMap::new();
+        labels.insert(String::from("external"), external.to_string());
+        Ok(meter::Measurements {
+            instrument: meter::Instrument::InterruptionDaily,
+            labels,
+            data: meter::count_daily(&created, timezone),
+        })
+    }
+    fn query_break_daily(
+        &mut self,
+        range: &meter::TimeRange,
+        timezone: &Tz,
+    ) -> Result<meter::Measurements> {
+        let rested: Vec<(DateTime<Utc>, f64)> =
+            breaks::Fetch::fetch_breaks_between(self, &range.start, &range.end)?
+                .iter()
+                .map(|b| (b.started_at, b.rested_min() as f64))
+                .collect();
+        Ok(meter::Measurements {
+            instrument: meter::Instrument::BreakDaily,
+            labels: HashMap::new(),
+            data: meter::sum_daily(&rested, timezone),
         })
     }
 }
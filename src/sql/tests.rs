@@ -1,16 +1,22 @@
-use super::lane::Fetch as LaneFetch;
+use super::lane::{self, Fetch as LaneFetch};
 use super::meter;
-use super::priority::Fetch as PriorityFetch;
-use super::task::{Add, Fetch as TaskFetch, Mod as TaskMod, Task};
+use super::priority::{self, Fetch as PriorityFetch};
+use super::tag;
+use super::tag::Fetch as TagFetch;
+use super::task::{self, Add, Fetch as TaskFetch, Mod as TaskMod, Task};
 use super::timer;
 use super::todo;
 use super::Session;
+use crate::core::breaks;
+use crate::core::estimate;
+use crate::core::interruption;
 use crate::core::meter::MeterQuery;
 use crate::core::pomodoro;
-use crate::core::Id;
+use crate::core::{Id, RepositoryError};
 use anyhow::Result;
 use chrono::{DateTime, TimeZone, Utc};
-use rusqlite::Connection;
+use chrono_tz::Tz;
+use rusqlite::{params, Connection};
 
 const TASK_SUMMARY: &str = "test1";
 
@@ -32,11 +38,44 @@ fn fetch_first_created_task(session: &mut Session) -> Result<Task> {
     Ok(task)
 }
 
+fn repository_error<T>(result: Result<T>) -> Option<RepositoryError> {
+    result.err()?.downcast_ref::<RepositoryError>().cloned()
+}
+
 fn add_test_task(session: &mut Session) -> Result<()> {
-    let _ = session.add_task(1, 0, TASK_SUMMARY, 3)?;
+    session.add_task(1, 0, TASK_SUMMARY, 3)?;
+    Ok(())
+}
+
+#[test]
+fn test_initialize_twice() -> Result<()> {
+    let mut session = get_initialized_session();
+    session.initialize()?;
+    assert_eq!(session.schema_version()?, super::ddl::latest_version());
+    assert_eq!(session.fetch_all_lanes()?.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_migrate_applies_pending_only() -> Result<()> {
+    let mut session = connect_memory()?;
+    assert_eq!(session.schema_version()?, 0);
+    let applied = session.migrate()?;
+    assert_eq!(applied.len(), super::ddl::MIGRATIONS.len());
+    assert!(session.pending_migrations()?.is_empty());
+    assert!(session.migrate()?.is_empty());
     Ok(())
 }
 
+#[test]
+fn test_check_schema_points_at_migrate() -> Result<()> {
+    let mut session = connect_memory()?;
+    let message = session.check_schema().unwrap_err().to_string();
+    assert!(message.contains("ly db migrate"), "{}", message);
+    session.migrate()?;
+    session.check_schema()
+}
+
 #[test]
 fn test_fetch_lane() -> Result<()> {
     let mut session = get_initialized_session();
@@ -47,6 +86,61 @@ fn test_fetch_lane() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_add_rename_reorder_lanes() -> Result<()> {
+    let mut session = get_initialized_session();
+    lane::add_lane(&mut session, "review")?;
+    assert_eq!(
+        repository_error(lane::add_lane(&mut session, "review")),
+        Some(RepositoryError::LaneAlreadyExists(String::from("review")))
+    );
+    assert!(lane::add_lane(&mut session, " ").is_err());
+    lane::rename_lane(&mut session, "review", "waiting")?;
+    assert!(lane::rename_lane(&mut session, "waiting", "todo").is_err());
+
+    let names = |s: &mut Session| -> Result<Vec<String>> {
+        Ok(s.fetch_all_lanes()?.into_iter().map(|l| l.name).collect())
+    };
+    assert_eq!(names(&mut session)?, ["backlog", "todo", "done", "waiting"]);
+    let order: Vec<String> = ["backlog", "todo", "waiting", "done"]
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+    lane::reorder_lanes(&mut session, &order)?;
+    assert_eq!(names(&mut session)?, order);
+    assert!(lane::reorder_lanes(&mut session, &order[1..]).is_err());
+    let twice: Vec<String> = ["backlog", "todo", "todo", "done"]
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+    assert_eq!(
+        repository_error(lane::reorder_lanes(&mut session, &twice)),
+        Some(RepositoryError::LaneListedTwice(String::from("todo")))
+    );
+    assert_eq!(names(&mut session)?, order);
+    Ok(())
+}
+
+#[test]
+fn test_remove_lane_with_tasks() -> Result<()> {
+    use crate::core::task::Remove;
+    let mut session = get_initialized_session();
+    let id = lane::add_lane(&mut session, "someday")?;
+    session.add_task(id, 0, "task in someday", 1)?;
+    session.add_task(id, 0, "archived in someday", 1)?;
+    session.archive_task(2)?;
+    assert!(lane::remove_lane(&mut session, "someday", None).is_err());
+    assert!(lane::remove_lane(&mut session, "someday", Some("someday")).is_err());
+    lane::remove_lane(&mut session, "someday", Some("backlog"))?;
+    assert!(session.fetch_lane_by_name("someday")?.is_none());
+    assert_eq!(session.fetch_all_tasks("backlog")?.len(), 1);
+    assert_eq!(session.fetch_task_by_id(2)?.map(|t| t.lane_id), Some(1));
+
+    lane::add_lane(&mut session, "empty")?;
+    lane::remove_lane(&mut session, "empty", None)?;
+    Ok(())
+}
+
 #[test]
 fn test_insert_fetch_task_by_id() -> Result<()> {
     let mut session = get_initialized_session();
@@ -63,7 +157,7 @@ fn test_insert_fetch_task_by_id() -> Result<()> {
 fn test_insert_fetch_all_tasks() -> Result<()> {
     let mut session = get_initialized_session();
     add_test_task(&mut session)?;
-    let _ = session.add_task(1, 1, "test2", 3)?;
+    session.add_task(1, 1, "test2", 3)?;
     let backlog = session.fetch_all_tasks("backlog")?;
 
     assert_eq!(backlog.len(), 2);
@@ -83,8 +177,113 @@ fn test_insert_fetch_all_tasks() -> Result<()> {
 #[test]
 fn test_fetch_priority() -> Result<()> {
     let mut session = get_initialized_session();
-    let h = session.fetch_priority_by_name("h")?;
-    assert_eq!(h.name, "h");
+    let h = session
+        .fetch_priority_by_name("h")?
+        .expect("alias should be resolved");
+    assert_eq!(h.name, "high");
+    assert_eq!(
+        session.fetch_priority_by_name("high")?.map(|p| p.id),
+        Some(h.id)
+    );
+    let names: Vec<String> = session
+        .fetch_all_priority()?
+        .into_iter()
+        .map(|p| p.name)
+        .collect();
+    assert_eq!(names, ["high", "medium", "low", "none"]);
+
+    // alias colliding with other name was accepted before
+    session.conn.execute(
+        "UPDATE priorities SET alias = 'high' WHERE name = 'none'",
+        [],
+    )?;
+    assert_eq!(
+        session.fetch_priority_by_name("high")?.map(|p| p.name),
+        Some(String::from("high")),
+        "exact name is preferred to alias"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_manage_priorities() -> Result<()> {
+    let mut session = get_initialized_session();
+    priority::add_priority(&mut session, "urgent", Some("u"))?;
+    assert!(priority::add_priority(&mut session, "h", None).is_err());
+    assert!(priority::add_priority(&mut session, "later", Some("later")).is_err());
+    assert_eq!(session.fetch_all_priority()?[0].name, "urgent");
+
+    priority::rename_priority(&mut session, "u", Some("asap"), None)?;
+    assert_eq!(
+        session.fetch_priority_by_name("u")?.map(|p| p.name),
+        Some(String::from("asap"))
+    );
+    assert!(priority::rename_priority(&mut session, "asap", None, Some("m")).is_err());
+    assert_eq!(
+        repository_error(priority::rename_priority(
+            &mut session,
+            "asap",
+            None,
+            Some("medium")
+        )),
+        Some(RepositoryError::PriorityAlreadyExists(String::from(
+            "medium"
+        )))
+    );
+    assert_eq!(
+        repository_error(priority::add_priority(&mut session, "m", Some("soon"))),
+        Some(RepositoryError::PriorityAlreadyExists(String::from("m")))
+    );
+
+    let order: Vec<String> = ["n", "l", "m", "h", "u"]
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+    priority::reorder_priorities(&mut session, &order)?;
+    assert_eq!(session.fetch_all_priority()?[0].name, "none");
+    assert!(priority::reorder_priorities(&mut session, &order[1..]).is_err());
+    let twice: Vec<String> = ["n", "low", "l", "h", "u"]
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+    assert_eq!(
+        repository_error(priority::reorder_priorities(&mut session, &twice)),
+        Some(RepositoryError::PriorityListedTwice(String::from("low")))
+    );
+    assert_eq!(session.fetch_all_priority()?[0].name, "none");
+    Ok(())
+}
+
+#[test]
+fn test_tasks_follow_priority_rank() -> Result<()> {
+    let mut session = get_initialized_session();
+    task::add_task(&mut session, "backlog", "low", "later", 1)?;
+    task::add_task(&mut session, "backlog", "h", "first", 1)?;
+    let order: Vec<String> = ["l", "h", "m", "n"].iter().map(|n| n.to_string()).collect();
+    priority::reorder_priorities(&mut session, &order)?;
+    let tasks = task::list_all_tasks(&mut session, "backlog")?;
+    assert_eq!(tasks[0].summary, "later");
+    Ok(())
+}
+
+#[test]
+fn test_mod_task_rejects_unknown_priority() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    assert_eq!(
+        repository_error(task::mod_task(&mut session, 1, None, Some("x"), None, None)),
+        Some(RepositoryError::PriorityNotFound(String::from("x")))
+    );
+    assert_eq!(
+        repository_error(task::mod_task(&mut session, 1, Some("x"), None, None, None)),
+        Some(RepositoryError::LaneNotFound(String::from("x")))
+    );
+    assert_eq!(
+        repository_error(task::mod_task(&mut session, 9, None, Some("h"), None, None)),
+        Some(RepositoryError::TaskNotFound(9))
+    );
+    task::mod_task(&mut session, 1, None, Some("h"), None, None)?;
+    assert_eq!(fetch_first_created_task(&mut session)?.priority, 3);
     Ok(())
 }
 
@@ -102,7 +301,7 @@ fn test_mod_task_move_lane() -> Result<()> {
 fn test_mod_task_higher_priority_and_new_summary() -> Result<()> {
     let mut session = get_initialized_session();
     add_test_task(&mut session)?;
-    let _ = session.mod_task(1, None, Some(&3), Some("test1 new"), None)?;
+    session.mod_task(1, None, Some(&3), Some("test1 new"), None)?;
     let t = fetch_first_created_task(&mut session)?;
     assert_eq!(t.priority, 3);
     assert_eq!(t.summary, "test1 new");
@@ -116,9 +315,8 @@ fn test_mod_plan_add_task() -> Result<()> {
     add_test_task(&mut session)?;
     let d = Utc.ymd(2015, 3, 14).and_hms(0, 0, 0);
     let a = vec![first_task_id];
-    let r = Vec::new();
-    let _ = todo::mod_todo(&mut session, &d, &a, &r)?;
-    let ts = todo::list_todo_tasks(&mut session, &d)?;
+    todo::replace_todo(&mut session, &d, &a, None)?;
+    let ts = todo::list_todo_tasks(&mut session, &d, &Utc)?;
     assert_eq!(ts[0].priority, 0);
     assert_eq!(ts[0].estimate, 3);
     assert_eq!(ts[0].actual, 0);
@@ -133,19 +331,89 @@ fn test_mod_plan_remove_task() -> Result<()> {
     add_test_task(&mut session)?;
     let d = Utc.ymd(2015, 3, 14).and_hms(0, 0, 0);
     let include_task = vec![first_task_id];
-    let empty = Vec::new();
-    let _ = todo::mod_todo(&mut session, &d, &include_task, &empty)?;
-    let _ = todo::mod_todo(&mut session, &d, &empty, &include_task)?;
-    let ts = todo::list_todo_tasks(&mut session, &d)?;
+    todo::replace_todo(&mut session, &d, &include_task, None)?;
+    todo::remove_todo_task(&mut session, &d, &Utc, first_task_id)?;
+    let ts = todo::list_todo_tasks(&mut session, &d, &Utc)?;
     assert_eq!(ts.len(), 0);
     Ok(())
 }
 
-fn complete_pomodoro<R>(r: &mut R, task_id: Id, started_at: DateTime<Utc>) -> Result<()>
-where
-    R: pomodoro::Complete,
-{
-    r.complete_pomodoro(task_id, started_at)
+#[test]
+fn test_edit_plan() -> Result<()> {
+    let mut session = get_initialized_session();
+    for _ in 0..3 {
+        add_test_task(&mut session)?;
+    }
+    let d = Utc.ymd(2015, 3, 14).and_hms(0, 0, 0);
+    let ids = |session: &mut Session| -> Result<Vec<Id>> {
+        let plan = todo::get_plan(session, &d, &Utc)?;
+        Ok(plan.tasks.iter().map(|t| t.task_id).collect())
+    };
+    todo::replace_todo(&mut session, &d, &[2, 1], None)?;
+    todo::append_todo_task(&mut session, &d, &Utc, 3)?;
+    assert_eq!(ids(&mut session)?, vec![2, 1, 3]);
+    assert!(todo::append_todo_task(&mut session, &d, &Utc, 3).is_err());
+    assert!(todo::replace_todo(&mut session, &d, &[1, 4], None).is_err());
+    assert!(todo::reorder_todo(&mut session, &d, &Utc, &[1, 2]).is_err());
+    todo::reorder_todo(&mut session, &d, &Utc, &[3, 2, 1])?;
+    assert_eq!(ids(&mut session)?, vec![3, 2, 1]);
+    todo::remove_todo_task(&mut session, &d, &Utc, 2)?;
+    assert_eq!(ids(&mut session)?, vec![3, 1]);
+    assert!(todo::remove_todo_task(&mut session, &d, &Utc, 2).is_err());
+    task::remove_task(&mut session, 1)?;
+    todo::replace_todo(&mut session, &d, &[3], None)?;
+    task::restore_task(&mut session, 1)?;
+    assert_eq!(
+        ids(&mut session)?,
+        vec![3],
+        "archived task is unplanned as well"
+    );
+
+    todo::set_note(&mut session, &d, "focus")?;
+    assert_eq!(todo::get_plan(&mut session, &d, &Utc)?.note, "focus");
+    let other = d + chrono::Duration::days(1);
+    todo::set_note(&mut session, &other, "rest")?;
+    assert_eq!(todo::get_plan(&mut session, &other, &Utc)?.note, "rest");
+    Ok(())
+}
+
+fn record_pomodoro(
+    session: &mut Session,
+    task_id: Id,
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    outcome: &pomodoro::Outcome,
+) -> Result<()> {
+    session.conn.execute(
+        "INSERT INTO pomodoros(task_id, started_at, finished_at, outcome) VALUES (?, ?, ?, ?)",
+        params![task_id, started_at, finished_at, outcome],
+    )?;
+    Ok(())
+}
+
+fn complete_pomodoro(session: &mut Session, task_id: Id, started_at: DateTime<Utc>) -> Result<()> {
+    let finished_at = started_at + chrono::Duration::minutes(25);
+    record_pomodoro(
+        session,
+        task_id,
+        started_at,
+        finished_at,
+        &pomodoro::Outcome::Completed,
+    )
+}
+
+fn record_break(
+    session: &mut Session,
+    timer_type: &timer::TimerType,
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    duration_min: i64,
+) -> Result<()> {
+    session.conn.execute(
+        super::ADD_BREAK,
+        params![timer_type, started_at, finished_at, duration_min, 0],
+    )?;
+    Ok(())
 }
 
 fn fetch_by_task_id<R>(r: &mut R, task_id: Id) -> Result<Vec<pomodoro::Pomodoro>>
@@ -168,6 +436,64 @@ fn test_fetch_pomodoro_by_task_id() -> Result<()> {
     assert_eq!(pomodoros[0].started_at, now, "started_at");
     Ok(())
 }
+#[test]
+fn test_count_completed_pomodoros() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    add_test_task(&mut session)?;
+    add_test_task(&mut session)?;
+    let d = Utc.ymd(2015, 3, 14).and_hms(0, 0, 0);
+    complete_pomodoro(&mut session, 1, d)?;
+    complete_pomodoro(&mut session, 1, d + chrono::Duration::hours(1))?;
+    complete_pomodoro(&mut session, 2, d)?;
+    record_pomodoro(
+        &mut session,
+        3,
+        d,
+        d + chrono::Duration::minutes(10),
+        &pomodoro::Outcome::Voided,
+    )?;
+    let counts = pomodoro::count_completed(&mut session)?;
+    assert_eq!(counts.get(&1), Some(&2));
+    assert_eq!(counts.get(&2), Some(&1));
+    assert_eq!(counts.get(&3), None, "voided pomodoro is not counted");
+    Ok(())
+}
+
+#[test]
+fn test_get_task_detail() -> Result<()> {
+    use crate::core::interruption::Add as InterruptionAdd;
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    let d = Utc.ymd(2015, 3, 14).and_hms(0, 0, 0);
+    let next = d + chrono::Duration::days(1);
+    todo::replace_todo(&mut session, &next, &[1], None)?;
+    todo::replace_todo(&mut session, &d, &[1], None)?;
+    complete_pomodoro(&mut session, 1, d)?;
+    record_pomodoro(
+        &mut session,
+        1,
+        next,
+        next + chrono::Duration::minutes(10),
+        &pomodoro::Outcome::Voided,
+    )?;
+    session.add_interruption(1, true, Some("phone call"), next)?;
+
+    let detail = task::get_detail(&mut session, 1)?;
+    assert_eq!(detail.lane_name, "backlog");
+    assert_eq!(detail.priority_name, "none");
+    assert_eq!(detail.actual, 1);
+    assert_eq!(detail.spent_sec, 35 * 60);
+    assert_eq!(detail.pomodoros.len(), 2);
+    assert_eq!(detail.interruptions.len(), 1);
+    assert_eq!(detail.todo_dates, vec![d, next]);
+    assert_eq!(
+        repository_error(task::get_detail(&mut session, 99)),
+        Some(RepositoryError::TaskNotFound(99))
+    );
+    Ok(())
+}
+
 #[test]
 fn test_fetch_todo_task_with_pomodoro() -> Result<()> {
     let first_task_id = 1;
@@ -175,13 +501,12 @@ fn test_fetch_todo_task_with_pomodoro() -> Result<()> {
     add_test_task(&mut session)?;
     let d = Utc.ymd(2015, 3, 14).and_hms(0, 0, 0);
     let a = vec![first_task_id];
-    let r = Vec::new();
-    let _ = todo::mod_todo(&mut session, &d, &a, &r)?;
+    todo::replace_todo(&mut session, &d, &a, None)?;
 
     let started = Utc.ymd(2015, 3, 14).and_hms(1, 0, 0);
     complete_pomodoro(&mut session, first_task_id, started)?;
 
-    let ts = todo::list_todo_tasks(&mut session, &d)?;
+    let ts = todo::list_todo_tasks(&mut session, &d, &Utc)?;
     assert_eq!(ts.len(), 1);
     assert_eq!(ts[0].priority, 0, "priority");
     assert_eq!(ts[0].estimate, 3, "estimate");
@@ -216,6 +541,19 @@ fn test_start_pomodoro() -> Result<()> {
     assert_eq!(created_timer.timer_type, timer::TimerType::Pomodoro);
     assert_eq!(created_timer.duration_min, duration_min);
     assert_eq!(created_timer.label, TASK_SUMMARY);
+    let second = timer::take_break(&mut session, &timer::TimerType::ShortBreak, duration_min);
+    assert!(matches!(
+        second.unwrap_err().downcast_ref(),
+        Some(crate::core::RepositoryError::TimerAlreadyRunning)
+    ));
+    let third = timer::pomodoro(&mut session, first_task_id, duration_min);
+    assert!(matches!(
+        third.unwrap_err().downcast_ref(),
+        Some(crate::core::RepositoryError::TimerAlreadyRunning)
+    ));
+    let timer_task = timer::TimerTaskGet::get_timer_task(&mut session)?
+        .expect("timer task of running pomodoro not found");
+    assert_eq!(timer_task.task_id, first_task_id);
     Ok(())
 }
 
@@ -230,7 +568,7 @@ fn test_fetch_daily_summary() -> Result<()> {
         start: Utc.ymd(2015, 3, 14).and_hms(1, 0, 0),
         end: Utc.ymd(2015, 3, 15).and_hms(1, 0, 0),
     };
-    let measurements = session.query_pomodoro_daily(&range)?;
+    let measurements = session.query_pomodoro_daily(&range, &Tz::Asia__Tokyo)?;
     assert_eq!(measurements.data.len(), 1);
     assert_eq!(
         measurements.data[0].0,
@@ -262,7 +600,7 @@ fn test_fetch_multiple_daily_summary() -> Result<()> {
         start: Utc.ymd(2015, 3, 14).and_hms(0, 0, 0),
         end: Utc.ymd(2015, 3, 14).and_hms(17, 0, 0),
     };
-    let measurements = session.query_pomodoro_daily(&range)?;
+    let measurements = session.query_pomodoro_daily(&range, &Tz::Asia__Tokyo)?;
     assert_eq!(measurements.data.len(), 2);
 
     for (i, answer) in [(2015, 3, 14, 0, 0, 0, 1.0), (2015, 3, 15, 0, 0, 0, 2.0)]
@@ -279,3 +617,607 @@ fn test_fetch_multiple_daily_summary() -> Result<()> {
     }
     Ok(())
 }
+
+#[test]
+fn test_fetch_daily_summary_in_dst_zone() -> Result<()> {
+    let first_task_id = 1;
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    // 2021-03-28 00:30 CET and 2021-03-28 23:30 CEST
+    for started in [
+        Utc.ymd(2021, 3, 27).and_hms(23, 30, 0),
+        Utc.ymd(2021, 3, 28).and_hms(21, 30, 0),
+    ] {
+        complete_pomodoro(&mut session, first_task_id, started)?;
+    }
+    let range = meter::TimeRange {
+        start: Utc.ymd(2021, 3, 27).and_hms(0, 0, 0),
+        end: Utc.ymd(2021, 3, 29).and_hms(0, 0, 0),
+    };
+    let measurements = session.query_pomodoro_daily(&range, &Tz::Europe__Berlin)?;
+    assert_eq!(measurements.data.len(), 1);
+    assert_eq!(
+        measurements.data[0].0,
+        Utc.ymd(2021, 3, 28).and_hms(0, 0, 0)
+    );
+    assert_eq!(measurements.data[0].1, 2.0, "pomodoro_count");
+    Ok(())
+}
+
+#[test]
+fn test_remove_and_restore_task() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    task::remove_task(&mut session, 1)?;
+    assert!(session.fetch_all_tasks("backlog")?.is_empty());
+    let archived = session.fetch_archived_tasks()?;
+    assert_eq!(archived.len(), 1);
+    assert!(archived[0].archived_at.is_some());
+    task::restore_task(&mut session, 1)?;
+    assert_eq!(session.fetch_all_tasks("backlog")?.len(), 1);
+    assert!(session.fetch_archived_tasks()?.is_empty());
+    assert_eq!(
+        repository_error(task::restore_task(&mut session, 1)),
+        Some(RepositoryError::TaskNotArchived(1))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_remove_task_on_current_timer() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    timer::pomodoro(&mut session, 1, 25)?;
+    assert_eq!(
+        repository_error(task::remove_task(&mut session, 1)),
+        Some(RepositoryError::TaskOnTimer(1))
+    );
+    assert_eq!(session.fetch_all_tasks("backlog")?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_purge_archived_task() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    session.add_task(1, 0, "test2", 1)?;
+    let d = Utc.ymd(2015, 3, 14).and_hms(0, 0, 0);
+    todo::replace_todo(&mut session, &d, &[1, 2], None)?;
+    complete_pomodoro(&mut session, 1, d)?;
+    task::remove_task(&mut session, 1)?;
+
+    let purged = task::purge_tasks(&mut session, &(Utc::now() - chrono::Duration::days(1)))?;
+    assert!(purged.is_empty(), "recently archived task is kept");
+
+    let purged = task::purge_tasks(&mut session, &(Utc::now() + chrono::Duration::days(1)))?;
+    assert_eq!(purged, vec![1]);
+    assert!(session.fetch_task_by_id(1)?.is_none());
+    assert!(fetch_by_task_id(&mut session, 1)?.is_empty());
+    let ts = todo::list_todo_tasks(&mut session, &d, &Utc)?;
+    assert_eq!(ts.len(), 1);
+    assert_eq!(ts[0].task_id, 2);
+    Ok(())
+}
+
+#[test]
+fn test_tag_and_untag_task() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    session.add_task(1, 0, "test2", 1)?;
+    tag::add_tag(&mut session, "project-a")?;
+    tag::tag_task(&mut session, 1, "project-a")?;
+    tag::tag_task(&mut session, 1, "project-a")?;
+
+    let tagged = tag::list_tagged_tasks(&mut session, "backlog", "project-a")?;
+    assert_eq!(tagged.len(), 1);
+    assert_eq!(tagged[0].id, 1);
+    let task_tags = session.fetch_all_task_tags()?;
+    assert_eq!(task_tags.len(), 1);
+    assert_eq!(task_tags[0].name, "project-a");
+
+    tag::untag_task(&mut session, 1, "project-a")?;
+    assert!(session.fetch_all_task_tags()?.is_empty());
+    assert!(tag::tag_task(&mut session, 1, "unknown").is_err());
+    assert_eq!(
+        repository_error(tag::untag_task(&mut session, 99, "project-a")),
+        Some(RepositoryError::TaskNotFound(99))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_name_is_reported() -> Result<()> {
+    use crate::core::tag::Add as TagAdd;
+    let mut session = get_initialized_session();
+    session.add_tag("project-a")?;
+    assert_eq!(
+        repository_error(session.add_tag("project-a")),
+        Some(RepositoryError::TagAlreadyExists(String::from("project-a")))
+    );
+    assert_eq!(
+        repository_error(tag::add_tag(&mut session, "project-a")),
+        Some(RepositoryError::TagAlreadyExists(String::from("project-a")))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_remove_tag_detaches_tasks() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    tag::add_tag(&mut session, "project-a")?;
+    tag::tag_task(&mut session, 1, "project-a")?;
+    tag::remove_tag(&mut session, "project-a")?;
+    assert!(session.fetch_all_tags()?.is_empty());
+    assert!(session.fetch_all_task_tags()?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_interrupt_current_pomodoro() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    assert_eq!(
+        repository_error(interruption::interrupt(&mut session, false, None)),
+        Some(RepositoryError::TimerNotRunning)
+    );
+    timer::pomodoro(&mut session, 1, 25)?;
+    interruption::interrupt(&mut session, false, None)?;
+    interruption::interrupt(&mut session, true, Some("phone call"))?;
+    let interruptions = interruption::list_by_task(&mut session, 1)?;
+    assert_eq!(interruptions.len(), 2);
+    assert!(!interruptions[0].external);
+    assert!(interruptions[1].external);
+    assert_eq!(interruptions[1].note.as_deref(), Some("phone call"));
+    Ok(())
+}
+
+#[test]
+fn test_fetch_todo_task_with_interruptions() -> Result<()> {
+    use crate::core::interruption::Add as InterruptionAdd;
+    let first_task_id = 1;
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    let d = Utc.ymd(2015, 3, 14).and_hms(0, 0, 0);
+    todo::replace_todo(&mut session, &d, &[first_task_id], None)?;
+    let at = Utc.ymd(2015, 3, 14).and_hms(1, 0, 0);
+    session.add_interruption(first_task_id, false, None, at)?;
+    session.add_interruption(first_task_id, false, None, at)?;
+    session.add_interruption(first_task_id, true, None, at)?;
+    session.add_interruption(first_task_id, true, None, d + chrono::Duration::days(1))?;
+
+    let ts = todo::list_todo_tasks(&mut session, &d, &Utc)?;
+    assert_eq!(ts[0].internal_interruptions, 2, "internal");
+    assert_eq!(ts[0].external_interruptions, 1, "external");
+
+    let range = meter::TimeRange {
+        start: d,
+        end: d + chrono::Duration::days(2),
+    };
+    let measurements = session.query_interruption_daily(&range, &Tz::UTC, true)?;
+    assert_eq!(measurements.data.len(), 2);
+    assert_eq!(measurements.data[0].1, 1.0);
+    Ok(())
+}
+
+#[test]
+fn test_estimate_history() -> Result<()> {
+    let mut session = get_initialized_session();
+    task::add_task(&mut session, "backlog", "n", TASK_SUMMARY, 2)?;
+    task::mod_task(&mut session, 1, None, None, None, Some(3))?;
+    task::mod_task(&mut session, 1, None, None, None, Some(3))?;
+    task::mod_task(&mut session, 1, Some("done"), None, None, Some(4))?;
+    let estimates = estimate::list_estimates(&mut session, 1)?;
+    let values: Vec<i64> = estimates.iter().map(|e| e.value).collect();
+    assert_eq!(values, vec![2, 3, 4]);
+
+    complete_pomodoro(&mut session, 1, Utc::now())?;
+    let report = estimate::accuracy_report(&mut session, "done")?;
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].first, 2);
+    assert_eq!(report[0].last, 4);
+    assert_eq!(report[0].actual, 1);
+    assert_eq!(report[0].revisions, 2);
+    Ok(())
+}
+
+#[test]
+fn test_mod_task_rolls_back_with_estimate() -> Result<()> {
+    let mut session = get_initialized_session();
+    session.add_task(1, 0, TASK_SUMMARY, 2)?;
+    session.conn.execute_batch(
+        "CREATE TRIGGER reject_estimate BEFORE INSERT ON estimates WHEN NEW.value = 5
+         BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+    )?;
+    assert!(session.mod_task(1, None, None, None, Some(5)).is_err());
+    let values: Vec<i64> = estimate::list_estimates(&mut session, 1)?
+        .iter()
+        .map(|e| e.value)
+        .collect();
+    assert_eq!(values, vec![2]);
+    assert_eq!(session.fetch_task_by_id(1)?.unwrap().estimate, 2);
+    Ok(())
+}
+
+#[test]
+fn test_get_task() -> Result<()> {
+    let mut session = get_initialized_session();
+    let id = task::add_task(&mut session, "backlog", "n", TASK_SUMMARY, 2)?;
+    let t = task::get_task(&mut session, id)?;
+    assert_eq!(t.summary, TASK_SUMMARY);
+    let missing = task::get_task(&mut session, id + 1);
+    assert_eq!(
+        repository_error(missing),
+        Some(RepositoryError::TaskNotFound(id + 1))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_publish_events() -> Result<()> {
+    let (sender, mut receiver) = tokio::sync::broadcast::channel(8);
+    let mut session = get_initialized_session().with_events(sender);
+    add_test_task(&mut session)?;
+    let mut names = || -> Vec<&'static str> {
+        let mut names = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            names.push(event.name());
+        }
+        names
+    };
+
+    timer::pomodoro(&mut session, 1, 25)?;
+    timer::pause(&mut session, Utc::now())?;
+    timer::resume(&mut session, Utc::now())?;
+    timer::stop_current(&mut session, &pomodoro::Outcome::Voided)?;
+    assert_eq!(
+        names(),
+        vec![
+            "timer_started",
+            "timer_paused",
+            "timer_resumed",
+            "timer_voided"
+        ]
+    );
+
+    let today = todo::start_of_day_in_tz(Utc::now(), &Utc);
+    todo::replace_todo(&mut session, &today, &[1], Some("note"))?;
+    assert_eq!(names(), vec!["todo_changed"]);
+    assert_eq!(todo::get_plan(&mut session, &today, &Utc)?.note, "note");
+    Ok(())
+}
+
+#[test]
+fn test_complete_break() -> Result<()> {
+    let mut session = get_initialized_session();
+    let timer = timer::take_break(&mut session, &timer::TimerType::LongBreak, 15)?;
+    timer::stop(
+        &mut session,
+        &timer,
+        &pomodoro::Outcome::Completed,
+        Utc::now(),
+    )?;
+    assert!(timer::get_current_timer(&mut session)?.is_none());
+    let today = todo::start_of_day_in_tz(Utc::now(), &Utc);
+    let breaks = breaks::list_on_date(&mut session, &today, &Utc)?;
+    assert_eq!(breaks.len(), 1);
+    assert_eq!(breaks[0].timer_type, timer::TimerType::LongBreak);
+    assert_eq!(breaks[0].duration_min, 15);
+    assert_eq!(breaks[0].started_at, timer.started_at);
+    Ok(())
+}
+
+#[test]
+fn test_fetch_break_daily() -> Result<()> {
+    let mut session = get_initialized_session();
+    let started = Utc.ymd(2015, 3, 14).and_hms(1, 0, 0);
+    let short = timer::TimerType::ShortBreak;
+    record_break(
+        &mut session,
+        &short,
+        started,
+        started + chrono::Duration::minutes(5),
+        5,
+    )?;
+    let started = Utc.ymd(2015, 3, 14).and_hms(2, 0, 0);
+    record_break(
+        &mut session,
+        &short,
+        started,
+        started + chrono::Duration::minutes(3),
+        5,
+    )?;
+    let range = meter::TimeRange {
+        start: Utc.ymd(2015, 3, 14).and_hms(0, 0, 0),
+        end: Utc.ymd(2015, 3, 15).and_hms(0, 0, 0),
+    };
+    let measurements = session.query_break_daily(&range, &Tz::UTC)?;
+    assert_eq!(measurements.data.len(), 1);
+    assert_eq!(measurements.data[0].1, 8.0, "rested minutes");
+    Ok(())
+}
+
+#[test]
+fn test_next_in_cycle() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    let d = Utc.ymd(2015, 3, 14).and_hms(0, 0, 0);
+    todo::replace_todo(&mut session, &d, &[1], None)?;
+    let now = Utc.ymd(2015, 3, 14).and_hms(12, 0, 0);
+
+    match timer::next_in_cycle(&mut session, now, &Utc, 2)? {
+        timer::Next::Pomodoro(t) => assert_eq!(t.task_id, 1),
+        n => panic!("pomodoro expected but {:?}", n),
+    }
+
+    let short = timer::TimerType::ShortBreak;
+    let at = |h: u32, m: u32| Utc.ymd(2015, 3, 14).and_hms(h, m, 0);
+    complete_pomodoro(&mut session, 1, at(9, 0))?;
+    assert!(matches!(
+        timer::next_in_cycle(&mut session, now, &Utc, 2)?,
+        timer::Next::Break(timer::TimerType::ShortBreak)
+    ));
+    record_break(&mut session, &short, at(9, 25), at(9, 30), 5)?;
+    complete_pomodoro(&mut session, 1, at(9, 30))?;
+    assert!(matches!(
+        timer::next_in_cycle(&mut session, now, &Utc, 2)?,
+        timer::Next::Break(timer::TimerType::LongBreak)
+    ));
+    assert_eq!(
+        timer::suggest_break(&mut session, now, &Utc, 2)?,
+        timer::TimerType::LongBreak
+    );
+    record_break(
+        &mut session,
+        &timer::TimerType::LongBreak,
+        at(9, 55),
+        at(10, 10),
+        15,
+    )?;
+    match timer::next_in_cycle(&mut session, now, &Utc, 2)? {
+        timer::Next::Pomodoro(t) => assert_eq!(t.task_id, 1),
+        n => panic!("pomodoro expected but {:?}", n),
+    }
+    complete_pomodoro(&mut session, 1, at(10, 10))?;
+    assert_eq!(
+        timer::suggest_break(&mut session, now, &Utc, 2)?,
+        timer::TimerType::ShortBreak,
+        "set is reset after long break"
+    );
+    record_break(&mut session, &short, at(10, 35), at(10, 40), 5)?;
+    assert!(
+        timer::next_in_cycle(&mut session, now, &Utc, 2).is_err(),
+        "estimate of todo task is reached"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_void_and_cancel_timer() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    let today = todo::start_of_day_in_tz(Utc::now(), &Utc);
+    todo::replace_todo(&mut session, &today, &[1], None)?;
+
+    let voided = timer::pomodoro(&mut session, 1, 25)?;
+    assert_eq!(
+        repository_error(timer::stop_current(
+            &mut session,
+            &pomodoro::Outcome::Completed
+        )),
+        Some(RepositoryError::PomodoroNotFinished),
+        "completed before deadline"
+    );
+    timer::stop_current(&mut session, &pomodoro::Outcome::Voided)?;
+    assert_eq!(
+        repository_error(timer::stop(
+            &mut session,
+            &voided,
+            &pomodoro::Outcome::Voided,
+            Utc::now()
+        )),
+        Some(RepositoryError::TimerAlreadyStopped)
+    );
+    let pomodoros = fetch_by_task_id(&mut session, 1)?;
+    assert_eq!(pomodoros.len(), 1);
+    assert_eq!(pomodoros[0].outcome, pomodoro::Outcome::Voided);
+    let ts = todo::list_todo_tasks(&mut session, &today, &Utc)?;
+    assert_eq!(ts[0].actual, 0, "voided pomodoro is not counted");
+
+    timer::pomodoro(&mut session, 1, 25)?;
+    timer::stop_current(&mut session, &pomodoro::Outcome::Cancelled)?;
+    assert_eq!(
+        fetch_by_task_id(&mut session, 1)?.len(),
+        1,
+        "cancelled pomodoro is not recorded"
+    );
+
+    timer::take_break(&mut session, &timer::TimerType::ShortBreak, 5)?;
+    timer::stop_current(&mut session, &pomodoro::Outcome::Cancelled)?;
+    timer::take_break(&mut session, &timer::TimerType::LongBreak, 15)?;
+    timer::stop_current(&mut session, &pomodoro::Outcome::Voided)?;
+    assert!(
+        breaks::list_on_date(&mut session, &today, &Utc)?.is_empty(),
+        "only completed break is recorded"
+    );
+    assert!(timer::stop_current(&mut session, &pomodoro::Outcome::Voided).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_stop_keeps_newer_timer() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    let stale = timer::pomodoro(&mut session, 1, 25)?;
+    timer::stop_current(&mut session, &pomodoro::Outcome::Voided)?;
+    // timers are told apart by milliseconds of their start
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    let current = timer::pomodoro(&mut session, 1, 25)?;
+    assert_eq!(
+        repository_error(timer::stop(
+            &mut session,
+            &stale,
+            &pomodoro::Outcome::Voided,
+            Utc::now()
+        )),
+        Some(RepositoryError::TimerAlreadyStopped)
+    );
+    let running = timer::get_current_timer(&mut session)?.expect("timer is kept");
+    assert_eq!(running.started_at, current.started_at);
+    assert_eq!(fetch_by_task_id(&mut session, 1)?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_pause_and_resume_timer() -> Result<()> {
+    let mut session = get_initialized_session();
+    let started = timer::take_break(&mut session, &timer::TimerType::ShortBreak, 5)?;
+    assert_eq!(
+        repository_error(timer::resume(&mut session, Utc::now())),
+        Some(RepositoryError::TimerNotPaused)
+    );
+    let minute = |m: i64| started.started_at + chrono::Duration::minutes(m);
+    timer::pause(&mut session, minute(1))?;
+    assert_eq!(
+        repository_error(timer::pause(&mut session, minute(2))),
+        Some(RepositoryError::TimerPaused)
+    );
+    let paused = timer::get_current_timer(&mut session)?.expect("timer not found");
+    assert!(paused.is_paused());
+    assert_eq!(paused.remaining(minute(10)), chrono::Duration::minutes(4));
+
+    timer::resume(&mut session, minute(3))?;
+    let resumed = timer::get_current_timer(&mut session)?.expect("timer not found");
+    assert!(!resumed.is_paused());
+    assert_eq!(resumed.paused_sec, 120);
+    assert_eq!(resumed.remaining(minute(4)), chrono::Duration::minutes(3));
+    Ok(())
+}
+
+#[test]
+fn test_expire_timer() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    let started = timer::pomodoro(&mut session, 1, 25)?;
+    let minute = |m: i64| started.started_at + chrono::Duration::minutes(m);
+    assert!(timer::expire(&mut session, minute(10))?.is_none());
+    timer::pause(&mut session, minute(10))?;
+    assert!(
+        timer::expire(&mut session, minute(60))?.is_none(),
+        "paused timer does not expire"
+    );
+    timer::resume(&mut session, minute(15))?;
+    assert!(timer::expire(&mut session, minute(29))?.is_none());
+    let expired = timer::expire(&mut session, minute(60))?.expect("timer not expired");
+    assert_eq!(expired.started_at, started.started_at);
+    assert!(timer::get_current_timer(&mut session)?.is_none());
+    let pomodoros = fetch_by_task_id(&mut session, 1)?;
+    assert_eq!(pomodoros.len(), 1);
+    assert_eq!(pomodoros[0].finished_at, minute(30), "finished at deadline");
+    assert_eq!(pomodoros[0].paused_sec, 5 * 60);
+    assert_eq!(
+        task::get_detail(&mut session, 1)?.spent_sec,
+        25 * 60,
+        "paused time is not spent"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_stop_paused_pomodoro() -> Result<()> {
+    let mut session = get_initialized_session();
+    add_test_task(&mut session)?;
+    let started = timer::pomodoro(&mut session, 1, 25)?;
+    let minute = |m: i64| started.started_at + chrono::Duration::minutes(m);
+    timer::pause(&mut session, minute(1))?;
+    timer::resume(&mut session, minute(3))?;
+    let paused = timer::pause(&mut session, minute(10))?;
+    timer::stop(
+        &mut session,
+        &paused,
+        &pomodoro::Outcome::Voided,
+        minute(12),
+    )?;
+    let pomodoros = fetch_by_task_id(&mut session, 1)?;
+    assert_eq!(pomodoros[0].paused_sec, 4 * 60, "ongoing pause is counted");
+    assert_eq!(task::get_detail(&mut session, 1)?.spent_sec, 8 * 60);
+    Ok(())
+}
+
+#[test]
+fn test_stop_paused_break() -> Result<()> {
+    let mut session = get_initialized_session();
+    let started = timer::take_break(&mut session, &timer::TimerType::ShortBreak, 5)?;
+    let minute = |m: i64| started.started_at + chrono::Duration::minutes(m);
+    timer::pause(&mut session, minute(1))?;
+    let resumed = timer::resume(&mut session, minute(3))?;
+    timer::stop(
+        &mut session,
+        &resumed,
+        &pomodoro::Outcome::Completed,
+        minute(7),
+    )?;
+    let rested = breaks::Fetch::fetch_breaks_between(&mut session, &minute(-1), &minute(8))?;
+    assert_eq!(rested[0].paused_sec, 2 * 60);
+    assert_eq!(rested[0].rested_min(), 5, "pause is not counted as rest");
+    Ok(())
+}
+
+#[test]
+fn test_rpc_dispatch() -> Result<()> {
+    use crate::rpc;
+    let mut session = get_initialized_session();
+    let settings = rpc::Settings {
+        timezone: Tz::UTC,
+        pomodoro: 25,
+        short_break: 5,
+        long_break: 15,
+        long_break_interval: 4,
+    };
+    let mut send = |request: rpc::Request| -> Result<serde_json::Value> {
+        let sent: rpc::Request = serde_json::from_str(&serde_json::to_string(&request)?)?;
+        rpc::dispatch(&mut session, &settings, sent)
+    };
+    let id = send(rpc::Request::AddTask {
+        lane: String::from("backlog"),
+        priority: String::from("none"),
+        summary: String::from(TASK_SUMMARY),
+        estimate: 2,
+    })?;
+    assert_eq!(id, serde_json::json!(1));
+    let tasks: Vec<Task> = serde_json::from_value(send(rpc::Request::ListTasks {
+        lane: String::from("backlog"),
+    })?)?;
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].summary, TASK_SUMMARY);
+
+    let started: timer::Timer = serde_json::from_value(send(rpc::Request::Start {
+        task_id: 1,
+        duration_min: None,
+    })?)?;
+    assert_eq!(started.duration_min, 25, "duration defaults to settings");
+    let e = send(rpc::Request::Start {
+        task_id: 1,
+        duration_min: None,
+    })
+    .unwrap_err();
+    assert_eq!(
+        e.downcast_ref::<RepositoryError>(),
+        Some(&RepositoryError::TimerAlreadyRunning)
+    );
+    send(rpc::Request::Stop {
+        outcome: pomodoro::Outcome::Voided,
+        started_at: Some(started.started_at),
+    })?;
+    let timer = send(rpc::Request::GetTimer)?;
+    assert!(timer.is_null());
+    assert_eq!(
+        repository_error(send(rpc::Request::Stop {
+            outcome: pomodoro::Outcome::Voided,
+            started_at: Some(started.started_at),
+        })),
+        Some(RepositoryError::TimerAlreadyStopped)
+    );
+    assert_eq!(fetch_by_task_id(&mut session, 1)?.len(), 1);
+    Ok(())
+}
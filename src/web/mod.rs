@@ -3,29 +3,66 @@ use std::fmt::Display;
 use super::config;
 use super::core::meter;
 use super::core::meter::MeterQuery;
-use super::core::timer;
+use super::core::pomodoro::{self, Outcome};
+use super::core::{event, lane, priority, task, timer, todo, Id, RepositoryError};
+use super::daemon;
 use super::public;
+use super::rpc;
 use super::sql::Session;
-use actix_web::{get, web, App, HttpResponse, HttpResponseBuilder, HttpServer, Responder};
-use anyhow::{Error, Result};
-use tokio::sync::Mutex;
+use actix_web::{
+    delete, get, patch, post, put, web, App, HttpResponse, HttpResponseBuilder, HttpServer,
+    Responder,
+};
+use anyhow::{Context, Error, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Mutex};
+
+/// Number of events kept for subscriber which is slow to receive.
+const EVENT_CAPACITY: usize = 64;
 
 struct State {
     session: Mutex<Session>,
+    conf: config::Config,
+    events: broadcast::Sender<event::Event>,
 }
 
 #[derive(Debug)]
 enum WebApiError {
     TimerNotFound,
+    NotFound(String),
+    BadRequest(String),
+    Conflict(String),
     InternalError,
 }
 
+impl From<Error> for WebApiError {
+    /// Missing entity is 404, other [`RepositoryError`] like second timer or duplicate name is 409,
+    /// database failure is 500 and anything else is rejected input.
+    fn from(e: Error) -> Self {
+        match e.downcast_ref::<RepositoryError>() {
+            Some(re) if re.is_not_found() => WebApiError::NotFound(re.to_string()),
+            Some(re) => WebApiError::Conflict(re.to_string()),
+            None if e.downcast_ref::<rusqlite::Error>().is_some() => {
+                error!("{:#}", e);
+                WebApiError::InternalError
+            }
+            None => WebApiError::BadRequest(format!("{:#}", e)),
+        }
+    }
+}
+
 impl Display for WebApiError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             WebApiError::TimerNotFound => {
                 f.write_str("Timer not found").unwrap();
             }
+            WebApiError::NotFound(ref message)
+            | WebApiError::BadRequest(ref message)
+            | WebApiError::Conflict(ref message) => {
+                f.write_str(message).unwrap();
+            }
             WebApiError::InternalError => {
                 f.write_str("Serious Problem").unwrap();
             }
@@ -38,24 +75,24 @@ impl actix_web::error::ResponseError for WebApiError {
     fn status_code(&self) -> http::StatusCode {
         match *self {
             WebApiError::TimerNotFound => http::StatusCode::NOT_FOUND,
+            WebApiError::NotFound(_) => http::StatusCode::NOT_FOUND,
+            WebApiError::BadRequest(_) => http::StatusCode::BAD_REQUEST,
+            WebApiError::Conflict(_) => http::StatusCode::CONFLICT,
             WebApiError::InternalError => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
+    /// Body is `{"message": ...}` so that browser can show it.
     fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
-        let mut res = HttpResponseBuilder::new(self.status_code());
-        match *self {
-            WebApiError::TimerNotFound => res.append_header(("Content-Type", "text/plain")),
-            WebApiError::InternalError => res.append_header(("Content-Type", "text/plain")),
-        };
-        let body = actix_web::body::BoxBody::new(format!("{}", self));
-        res.body(body)
+        HttpResponseBuilder::new(self.status_code())
+            .json(serde_json::json!({ "message": self.to_string() }))
     }
 }
 
 #[get("/timer")]
-async fn get_timer(data: web::Data<State>) -> impl Responder {
+async fn get_timer(data: web::Data<State>) -> Result<web::Json<timer::Timer>, WebApiError> {
     let mut session = data.session.lock().await;
+    daemon::expire_timer(&mut *session)?;
     match timer::get_current_timer(&mut *session) {
         Ok(Some(c)) => Ok(web::Json(c)),
         Ok(None) => Err(WebApiError::TimerNotFound),
@@ -63,26 +100,469 @@ async fn get_timer(data: web::Data<State>) -> impl Responder {
     }
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum BreakType {
+    Short,
+    Long,
+}
+
+/// Body to start timer. Either `task_id` for pomodoro or `break_type` is required.
+/// Duration defaults to the one in config.
+#[derive(Deserialize)]
+struct StartTimer {
+    task_id: Option<Id>,
+    break_type: Option<BreakType>,
+    duration_min: Option<i64>,
+}
+
+#[post("/timer")]
+async fn start_timer(
+    data: web::Data<State>,
+    body: web::Json<StartTimer>,
+) -> Result<HttpResponse, WebApiError> {
+    let mut session = data.session.lock().await;
+    daemon::expire_timer(&mut *session)?;
+    let conf = &data.conf;
+    let started = match (body.task_id, &body.break_type) {
+        (Some(task_id), None) => timer::pomodoro(
+            &mut *session,
+            task_id,
+            body.duration_min.unwrap_or(conf.pomodoro),
+        )?,
+        (None, Some(BreakType::Short)) => timer::take_break(
+            &mut *session,
+            &timer::TimerType::ShortBreak,
+            body.duration_min.unwrap_or(conf.short_break),
+        )?,
+        (None, Some(BreakType::Long)) => timer::take_break(
+            &mut *session,
+            &timer::TimerType::LongBreak,
+            body.duration_min.unwrap_or(conf.long_break),
+        )?,
+        _ => {
+            return Err(WebApiError::BadRequest(String::from(
+                "either task_id or break_type is required",
+            )))
+        }
+    };
+    Ok(HttpResponse::Created().json(started))
+}
+
+/// Stops the running timer and returns it as it was.
+async fn stop_timer_with(
+    data: &State,
+    outcome: &Outcome,
+) -> Result<web::Json<timer::Timer>, WebApiError> {
+    let mut session = data.session.lock().await;
+    daemon::expire_timer(&mut *session)?;
+    match timer::get_current_timer(&mut *session)? {
+        Some(current) => {
+            timer::stop(&mut *session, &current, outcome, Utc::now())?;
+            Ok(web::Json(current))
+        }
+        None => Err(WebApiError::TimerNotFound),
+    }
+}
+
+#[derive(Deserialize)]
+struct StopQuery {
+    /// Record interrupted pomodoro instead of discarding it
+    #[serde(default)]
+    void: bool,
+}
+
+#[delete("/timer")]
+async fn stop_timer(
+    data: web::Data<State>,
+    query: web::Query<StopQuery>,
+) -> Result<web::Json<timer::Timer>, WebApiError> {
+    let outcome = if query.void {
+        Outcome::Voided
+    } else {
+        Outcome::Cancelled
+    };
+    stop_timer_with(&data, &outcome).await
+}
+
+#[post("/timer/complete")]
+async fn complete_timer(data: web::Data<State>) -> Result<web::Json<timer::Timer>, WebApiError> {
+    stop_timer_with(&data, &Outcome::Completed).await
+}
+
 #[get("/pomodoro_daily")]
 async fn query_pomodoro_daily(
     data: web::Data<State>,
     range: web::Query<meter::TimeRange>,
 ) -> impl Responder {
     let mut session = data.session.lock().await;
-    match session.query_pomodoro_daily(&range) {
+    match session.query_pomodoro_daily(&range, &data.conf.timezone) {
+        Ok(summaries) => Ok(web::Json(summaries)),
+        Err(_e) => Err(WebApiError::InternalError),
+    }
+}
+
+#[get("/interruption_daily")]
+async fn query_interruption_daily(
+    data: web::Data<State>,
+    range: web::Query<meter::TimeRange>,
+) -> impl Responder {
+    let mut session = data.session.lock().await;
+    let mut series = Vec::new();
+    for external in [false, true] {
+        match session.query_interruption_daily(&range, &data.conf.timezone, external) {
+            Ok(summaries) => series.push(summaries),
+            Err(_e) => return Err(WebApiError::InternalError),
+        }
+    }
+    Ok(web::Json(series))
+}
+
+#[get("/break_daily")]
+async fn query_break_daily(
+    data: web::Data<State>,
+    range: web::Query<meter::TimeRange>,
+) -> impl Responder {
+    let mut session = data.session.lock().await;
+    match session.query_break_daily(&range, &data.conf.timezone) {
         Ok(summaries) => Ok(web::Json(summaries)),
         Err(_e) => Err(WebApiError::InternalError),
     }
 }
 
-pub async fn start_server(conf: config::Config, address: String, port: u16) -> Result<()> {
-    let session = crate::sql::Session::connect(&conf)?;
+#[derive(Deserialize)]
+struct TaskQuery {
+    /// All lanes when omitted
+    lane: Option<String>,
+    #[serde(default)]
+    archived: bool,
+}
+
+/// Body to create task. Fields are named after [`task::Task`].
+#[derive(Deserialize)]
+struct NewTask {
+    lane_id: Id,
+    priority: Id,
+    summary: String,
+    estimate: i64,
+}
 
+/// Body to modify task. Omitted fields are kept.
+#[derive(Deserialize)]
+struct TaskPatch {
+    lane_id: Option<Id>,
+    priority: Option<Id>,
+    summary: Option<String>,
+    estimate: Option<i64>,
+}
+
+/// Core functions take lane and priority by name as CLI does.
+fn lane_name(session: &mut Session, id: Id) -> Result<String, WebApiError> {
+    let name = lane::fetch_all_lanes(session)?
+        .into_iter()
+        .find(|l| l.id == id)
+        .map(|l| l.name)
+        .ok_or_else(|| Error::from(RepositoryError::LaneNotFound(id.to_string())))?;
+    Ok(name)
+}
+
+fn priority_name(session: &mut Session, id: Id) -> Result<String, WebApiError> {
+    let name = priority::fetch_all_priority(session)?
+        .into_iter()
+        .find(|p| p.id == id)
+        .map(|p| p.name)
+        .ok_or_else(|| Error::from(RepositoryError::PriorityNotFound(id.to_string())))?;
+    Ok(name)
+}
+
+#[get("/tasks")]
+async fn list_tasks(
+    data: web::Data<State>,
+    query: web::Query<TaskQuery>,
+) -> Result<web::Json<Vec<task::Task>>, WebApiError> {
+    let mut session = data.session.lock().await;
+    if query.archived {
+        return Ok(web::Json(task::list_archived_tasks(&mut *session)?));
+    }
+    let names: Vec<String> = match &query.lane {
+        Some(name) => vec![lane::fetch_existing_lane(&mut *session, name)?.name],
+        None => lane::fetch_all_lanes(&mut *session)?
+            .into_iter()
+            .map(|l| l.name)
+            .collect(),
+    };
+    let mut tasks = Vec::new();
+    for name in names {
+        tasks.extend(task::list_all_tasks(&mut *session, &name)?);
+    }
+    Ok(web::Json(tasks))
+}
+
+/// Task with its pomodoros, interruptions and todo dates as `ly task show` prints.
+#[get("/tasks/{id}")]
+async fn get_task(
+    data: web::Data<State>,
+    id: web::Path<Id>,
+) -> Result<web::Json<task::Detail>, WebApiError> {
+    let mut session = data.session.lock().await;
+    Ok(web::Json(task::get_detail(&mut *session, id.into_inner())?))
+}
+
+#[post("/tasks")]
+async fn add_task(
+    data: web::Data<State>,
+    body: web::Json<NewTask>,
+) -> Result<HttpResponse, WebApiError> {
+    let mut session = data.session.lock().await;
+    let lane = lane_name(&mut session, body.lane_id)?;
+    let prio = priority_name(&mut session, body.priority)?;
+    let id = task::add_task(&mut *session, &lane, &prio, &body.summary, body.estimate)?;
+    let created = task::get_task(&mut *session, id)?;
+    Ok(HttpResponse::Created()
+        .append_header(("Location", format!("/api/tasks/{}", id)))
+        .json(created))
+}
+
+#[patch("/tasks/{id}")]
+async fn mod_task(
+    data: web::Data<State>,
+    id: web::Path<Id>,
+    body: web::Json<TaskPatch>,
+) -> Result<web::Json<task::Task>, WebApiError> {
+    let id = id.into_inner();
+    let mut session = data.session.lock().await;
+    let lane = match body.lane_id {
+        Some(lane_id) => Some(lane_name(&mut session, lane_id)?),
+        None => None,
+    };
+    let prio = match body.priority {
+        Some(prio_id) => Some(priority_name(&mut session, prio_id)?),
+        None => None,
+    };
+    task::mod_task(
+        &mut *session,
+        id,
+        lane.as_deref(),
+        prio.as_deref(),
+        body.summary.as_deref(),
+        body.estimate,
+    )?;
+    Ok(web::Json(task::get_task(&mut *session, id)?))
+}
+
+/// Archives task as `ly task rm` does.
+#[delete("/tasks/{id}")]
+async fn remove_task(
+    data: web::Data<State>,
+    id: web::Path<Id>,
+) -> Result<HttpResponse, WebApiError> {
+    let mut session = data.session.lock().await;
+    task::remove_task(&mut *session, id.into_inner())?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/lanes")]
+async fn list_lanes(data: web::Data<State>) -> Result<web::Json<Vec<lane::Lane>>, WebApiError> {
+    let mut session = data.session.lock().await;
+    Ok(web::Json(lane::fetch_all_lanes(&mut *session)?))
+}
+
+#[get("/priorities")]
+async fn list_priorities(
+    data: web::Data<State>,
+) -> Result<web::Json<Vec<priority::Priority>>, WebApiError> {
+    let mut session = data.session.lock().await;
+    Ok(web::Json(priority::fetch_all_priority(&mut *session)?))
+}
+
+/// Task on the board with count of its completed pomodoros.
+#[derive(Serialize)]
+struct Card {
+    #[serde(flatten)]
+    task: task::Task,
+    pomodoros: usize,
+}
+
+/// Tasks of a lane on the board.
+#[derive(Serialize)]
+struct Column {
+    lane: lane::Lane,
+    tasks: Vec<Card>,
+}
+
+/// Every lane in process order with its tasks.
+#[get("/board")]
+async fn get_board(data: web::Data<State>) -> Result<web::Json<Vec<Column>>, WebApiError> {
+    let mut session = data.session.lock().await;
+    let counts = pomodoro::count_completed(&mut *session)?;
+    let mut columns = Vec::new();
+    for lane in lane::fetch_all_lanes(&mut *session)? {
+        let mut tasks = Vec::new();
+        for task in task::list_all_tasks(&mut *session, &lane.name)? {
+            let pomodoros = counts.get(&task.id).copied().unwrap_or(0);
+            tasks.push(Card { task, pomodoros });
+        }
+        columns.push(Column { lane, tasks });
+    }
+    Ok(web::Json(columns))
+}
+
+/// Parses `YYYY-MM-DD` or `today` in path.
+fn todo_date(data: &State, input: &str) -> Result<todo::TodoDate, WebApiError> {
+    let input = Some(input).filter(|i| *i != "today");
+    let date = todo::parse_or_today(&data.conf.timezone, input)
+        .with_context(|| format!("invalid date {}", input.unwrap_or_default()))?;
+    Ok(date)
+}
+
+/// Body to replace todo of a day.
+#[derive(Deserialize)]
+struct PlanBody {
+    task_ids: Vec<Id>,
+    #[serde(default)]
+    note: String,
+}
+
+#[derive(Deserialize)]
+struct NoteBody {
+    note: String,
+}
+
+#[derive(Deserialize)]
+struct TodoTaskBody {
+    task_id: Id,
+}
+
+#[get("/todo/{date}")]
+async fn get_todo(
+    data: web::Data<State>,
+    date: web::Path<String>,
+) -> Result<web::Json<todo::Plan>, WebApiError> {
+    let date = todo_date(&data, &date)?;
+    let mut session = data.session.lock().await;
+    let plan = todo::get_plan(&mut *session, &date, &data.conf.timezone)?;
+    Ok(web::Json(plan))
+}
+
+#[put("/todo/{date}")]
+async fn put_todo(
+    data: web::Data<State>,
+    date: web::Path<String>,
+    body: web::Json<PlanBody>,
+) -> Result<web::Json<todo::Plan>, WebApiError> {
+    let date = todo_date(&data, &date)?;
+    let tz = &data.conf.timezone;
+    let mut session = data.session.lock().await;
+    todo::replace_todo(&mut *session, &date, &body.task_ids, Some(&body.note))?;
+    Ok(web::Json(todo::get_plan(&mut *session, &date, tz)?))
+}
+
+#[put("/todo/{date}/note")]
+async fn put_todo_note(
+    data: web::Data<State>,
+    date: web::Path<String>,
+    body: web::Json<NoteBody>,
+) -> Result<web::Json<todo::Plan>, WebApiError> {
+    let date = todo_date(&data, &date)?;
+    let tz = &data.conf.timezone;
+    let mut session = data.session.lock().await;
+    todo::set_note(&mut *session, &date, &body.note)?;
+    Ok(web::Json(todo::get_plan(&mut *session, &date, tz)?))
+}
+
+#[post("/todo/{date}/tasks")]
+async fn add_todo_task(
+    data: web::Data<State>,
+    date: web::Path<String>,
+    body: web::Json<TodoTaskBody>,
+) -> Result<web::Json<todo::Plan>, WebApiError> {
+    let date = todo_date(&data, &date)?;
+    let tz = &data.conf.timezone;
+    let mut session = data.session.lock().await;
+    todo::append_todo_task(&mut *session, &date, tz, body.task_id)?;
+    Ok(web::Json(todo::get_plan(&mut *session, &date, tz)?))
+}
+
+/// Reorders planned tasks. Body is the list of their ids in new order.
+#[put("/todo/{date}/tasks")]
+async fn reorder_todo(
+    data: web::Data<State>,
+    date: web::Path<String>,
+    body: web::Json<Vec<Id>>,
+) -> Result<web::Json<todo::Plan>, WebApiError> {
+    let date = todo_date(&data, &date)?;
+    let tz = &data.conf.timezone;
+    let mut session = data.session.lock().await;
+    todo::reorder_todo(&mut *session, &date, tz, &body)?;
+    Ok(web::Json(todo::get_plan(&mut *session, &date, tz)?))
+}
+
+#[delete("/todo/{date}/tasks/{task_id}")]
+async fn remove_todo_task(
+    data: web::Data<State>,
+    path: web::Path<(String, Id)>,
+) -> Result<web::Json<todo::Plan>, WebApiError> {
+    let (date, task_id) = path.into_inner();
+    let date = todo_date(&data, &date)?;
+    let tz = &data.conf.timezone;
+    let mut session = data.session.lock().await;
+    todo::remove_todo_task(&mut *session, &date, tz, task_id)?;
+    Ok(web::Json(todo::get_plan(&mut *session, &date, tz)?))
+}
+
+/// Pushes events as Server-Sent Events named after [`event::Event::name`].
+#[get("/events")]
+async fn stream_events(data: web::Data<State>) -> HttpResponse {
+    let receiver = data.events.subscribe();
+    let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => match serde_json::to_string(&event) {
+                    Ok(json) => {
+                        let chunk = format!("event: {}\ndata: {}\n\n", event.name(), json);
+                        return Some((
+                            Ok::<_, actix_web::Error>(web::Bytes::from(chunk)),
+                            receiver,
+                        ));
+                    }
+                    Err(e) => warn!("failed to serialize event: {:?}", e),
+                },
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("subscriber missed {} events", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    HttpResponse::Ok()
+        .append_header(("Content-Type", "text/event-stream"))
+        .append_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
+
+/// Command API for CLI client.
+async fn dispatch_rpc(data: web::Data<State>, call: web::Json<rpc::Call>) -> impl Responder {
+    let call = call.into_inner();
+    let mut session = data.session.lock().await;
+    let response: rpc::Response =
+        rpc::dispatch(&mut *session, &call.settings, call.request).map_err(rpc::Failure::from);
+    web::Json(response)
+}
+
+/// Serves web UI and API. Timers are completed at their deadline while the server is running.
+pub async fn start_server(conf: config::Config) -> Result<()> {
+    let (events, _) = broadcast::channel(EVENT_CAPACITY);
+    let session = Session::connect(&conf)?.with_events(events.clone());
+    session.check_schema()?;
     let state = State {
         session: Mutex::new(session),
+        conf: conf.clone(),
+        events,
     };
     let data = web::Data::new(state);
-    HttpServer::new(move || {
+    let watched = data.clone();
+    let watcher = daemon::watch_timer(&watched.session);
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(data.clone())
             .route(
@@ -109,14 +589,37 @@ pub async fn start_server(conf: config::Config, address: String, port: u16) -> R
                         .body(public::ALARM_MP3)
                 }),
             )
+            .route(rpc::PATH, web::post().to(dispatch_rpc))
             .service(
                 web::scope("/api")
                     .service(get_timer)
-                    .service(query_pomodoro_daily),
+                    .service(stream_events)
+                    .service(start_timer)
+                    .service(stop_timer)
+                    .service(complete_timer)
+                    .service(query_pomodoro_daily)
+                    .service(query_interruption_daily)
+                    .service(query_break_daily)
+                    .service(list_tasks)
+                    .service(get_task)
+                    .service(add_task)
+                    .service(mod_task)
+                    .service(remove_task)
+                    .service(list_lanes)
+                    .service(list_priorities)
+                    .service(get_board)
+                    .service(get_todo)
+                    .service(put_todo)
+                    .service(put_todo_note)
+                    .service(add_todo_task)
+                    .service(reorder_todo)
+                    .service(remove_todo_task),
             )
     })
-    .bind((address, port))?
-    .run()
-    .await
-    .map_err(Error::new)
+    .bind((conf.address.as_str(), conf.port))?
+    .run();
+    tokio::select! {
+        r = server => r.map_err(Error::new),
+        r = watcher => r,
+    }
 }